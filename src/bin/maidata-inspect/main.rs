@@ -1,12 +1,25 @@
+const USAGE: &str = "usage: $0 [stats | lint [--json] [--color]] <path/to/maidata.txt>";
+
 fn main() {
-    let filename = std::env::args()
-        .nth(1)
-        .expect("usage: $0 <path/to/maidata.txt>");
-    let content = read_file(&filename);
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [cmd, filename] if cmd == "stats" => stats(filename),
+        [cmd, flags @ .., filename] if cmd == "lint" => lint(filename, flags),
+        [filename] => inspect(filename),
+        _ => panic!("{}", USAGE),
+    }
+}
+
+fn inspect(filename: &str) {
+    let (content, encoding) = read_file(filename);
     let maidata = maidata::container::lex_maidata(&content);
 
+    println!("encoding = {}", encoding);
     println!("title = {}", maidata.title());
     println!("artist = {}", maidata.artist());
+    for warning in maidata.warnings() {
+        println!("warning: {}", warning);
+    }
 
     for diff in maidata.iter_difficulties() {
         use std::borrow::Cow;
@@ -39,8 +52,102 @@ fn main() {
     }
 }
 
-fn read_file<P: AsRef<std::path::Path>>(path: P) -> String {
-    let content = std::fs::read(path.as_ref()).expect("file reading failed");
-    String::from_utf8(content).expect("decoding file content as utf-8 failed")
+fn stats(filename: &str) {
+    use maidata::materialize::{ChartStats, NoteKind};
+
+    let (content, _) = read_file(filename);
+    let maidata = maidata::container::lex_maidata(&content);
+
+    println!("title = {}", maidata.title());
+    println!("artist = {}", maidata.artist());
+
+    for diff in maidata.iter_difficulties() {
+        let mut mcx = maidata::materialize::MaterializationContext::with_offset(0.0);
+        let notes = mcx.materialize_insns(diff.iter_insns());
+        let stats = ChartStats::from_notes(&notes);
+
+        println!();
+        println!("difficulty {:?}", diff.difficulty());
+        println!(
+            "  TAP {} / HOLD {} / SLIDE {} / BREAK {}",
+            stats.num_taps, stats.num_holds, stats.num_slides, stats.num_breaks,
+        );
+        println!("  max combo {}", stats.max_combo());
+        println!("  max DX score {}", stats.max_dx_score());
+        println!("  max achievement {:.4}%", stats.max_achievement());
+        println!("  achievement per note:");
+        for &kind in NoteKind::ALL {
+            if stats.count(kind) > 0 {
+                println!("    {:?} {:.4}%", kind, stats.achievement_per_note(kind));
+            }
+        }
+    }
 }
 
+fn lint(filename: &str, flags: &[String]) {
+    use maidata::diagnostic::{render_json, Diagnostic, Renderer};
+    use maidata::lint::{lint_insns, LintConfig, Severity};
+
+    let mut json = false;
+    let mut color = false;
+    for flag in flags {
+        match flag.as_str() {
+            "--json" => json = true,
+            "--color" => color = true,
+            _ => panic!("{}", USAGE),
+        }
+    }
+
+    let (content, _) = read_file(filename);
+    let mut diags = vec![];
+    match maidata::container::lex_entries(&content) {
+        Some(entries) => {
+            for entry in entries.iter().filter(|x| x.key.starts_with("inote_")) {
+                // spans are relative to the chart, make them point into the whole file
+                let origin = entry.value.span();
+
+                match maidata::insn::Cst::parse(&entry.value).lower() {
+                    Ok(insns) => {
+                        for x in lint_insns(&insns, &LintConfig::default()) {
+                            let mut diag = Diagnostic::from(&x);
+                            for label in &mut diag.labels {
+                                label.span = label.span.rebase(origin);
+                            }
+                            diags.push(diag);
+                        }
+                    }
+                    Err(spans) => diags.extend(spans.into_iter().map(|span| {
+                        Diagnostic::new(Severity::Error, "malformed instruction")
+                            .with_primary_label(span.rebase(origin), "")
+                            .with_note(format!("in the value of `&{}`", *entry.key))
+                    })),
+                }
+            }
+        }
+        None => diags.push(Diagnostic::new(
+            Severity::Error,
+            "expected `&key=value` entries",
+        )),
+    }
+
+    if json {
+        println!("{}", render_json(filename, &diags));
+    } else {
+        let renderer = Renderer::new(color);
+        for diag in &diags {
+            println!("{}", renderer.render(filename, &content, diag));
+        }
+    }
+
+    if diags.iter().any(|x| x.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+}
+
+fn read_file<P: AsRef<std::path::Path>>(path: P) -> (String, maidata::container::TextEncoding) {
+    let content = std::fs::read(path.as_ref()).expect("file reading failed");
+    match maidata::container::decode(&content, None) {
+        Ok(x) => x,
+        Err(e) => panic!("decoding file content failed: {}", e),
+    }
+}
@@ -0,0 +1,390 @@
+//! The on-disk song index, stored as JSON.
+
+use std::path::{Path, PathBuf};
+
+use maidata::json::{self, Json};
+use maidata::Level;
+
+/// Version of the index format, older indices are rebuilt from scratch.
+const VERSION: usize = 2;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SongRecord {
+    /// Directory holding the song.
+    pub path: PathBuf,
+    /// Modification time of `maidata.txt`, in milliseconds since the Unix epoch.
+    pub mtime: u64,
+    pub title: String,
+    pub artist: String,
+    pub min_bpm: Option<f32>,
+    pub max_bpm: Option<f32>,
+    pub charts: Vec<ChartRecord>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ChartRecord {
+    pub difficulty: maidata::Difficulty,
+    pub level: Option<Level>,
+    /// The level as written, for display of levels not understood, e.g. utage ones.
+    pub raw_level: Option<String>,
+    pub designer: Option<String>,
+    pub num_taps: u32,
+    pub num_holds: u32,
+    pub num_slides: u32,
+    pub num_breaks: u32,
+}
+
+impl ChartRecord {
+    pub fn total_notes(&self) -> u32 {
+        self.num_taps + self.num_holds + self.num_slides + self.num_breaks
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Index {
+    pub songs: Vec<SongRecord>,
+}
+
+/// Criteria songs must all meet to be listed; unset ones match anything.
+///
+/// Text is matched case-insensitively as substrings. The difficulty and levels must be met by
+/// the same chart.
+///
+/// Levels of the "Lv.X" and "Lv.X+" forms stand for all the chart constants they cover, so
+/// "13+" matches a chart declared as "13.7". Special levels only match themselves.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Query {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub designer: Option<String>,
+    pub difficulty: Option<maidata::Difficulty>,
+    pub level: Option<Level>,
+    pub min_level: Option<Level>,
+    pub max_level: Option<Level>,
+}
+
+#[derive(Debug)]
+pub enum IndexError {
+    Io(std::io::Error),
+    Json(json::ParseError),
+    /// The JSON is well-formed but not an index.
+    Malformed,
+}
+
+impl std::fmt::Display for IndexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexError::Io(e) => write!(f, "I/O error: {}", e),
+            IndexError::Json(e) => write!(f, "{}", e),
+            IndexError::Malformed => write!(f, "malformed index"),
+        }
+    }
+}
+
+impl From<std::io::Error> for IndexError {
+    fn from(x: std::io::Error) -> Self {
+        IndexError::Io(x)
+    }
+}
+
+impl Index {
+    /// Loads the index at `path`, which is empty if the file does not exist yet or is of an older
+    /// format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, IndexError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let x = json::parse(&content).map_err(IndexError::Json)?;
+        match x.get("version").and_then(Json::as_usize) {
+            Some(version) if version < VERSION => Ok(Self::default()),
+            _ => Self::from_json(&x).ok_or(IndexError::Malformed),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), IndexError> {
+        std::fs::write(path, self.to_json().to_string())?;
+        Ok(())
+    }
+
+    pub fn find(&self, path: &Path) -> Option<&SongRecord> {
+        self.songs.iter().find(|x| x.path == path)
+    }
+
+    pub fn query<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = &'a SongRecord> + 'a {
+        self.songs.iter().filter(move |x| query.matches(x))
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("version", Json::from(VERSION)),
+            (
+                "songs",
+                self.songs
+                    .iter()
+                    .map(song_to_json)
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ])
+    }
+
+    fn from_json(x: &Json) -> Option<Self> {
+        if x.get("version")?.as_usize()? != VERSION {
+            return None;
+        }
+
+        let songs = x
+            .get("songs")?
+            .as_array()?
+            .iter()
+            .map(song_from_json)
+            .collect::<Option<_>>()?;
+        Some(Self { songs })
+    }
+}
+
+impl Query {
+    pub fn matches(&self, song: &SongRecord) -> bool {
+        let contains = |haystack: &str, needle: &Option<String>| {
+            needle
+                .as_ref()
+                .is_none_or(|x| haystack.to_lowercase().contains(&x.to_lowercase()))
+        };
+
+        contains(&song.title, &self.title)
+            && contains(&song.artist, &self.artist)
+            && (self.designer.is_none()
+                || song
+                    .charts
+                    .iter()
+                    .any(|x| contains(x.designer.as_deref().unwrap_or(""), &self.designer)))
+            && song.charts.iter().any(|x| {
+                use std::cmp::Ordering::*;
+
+                let level_is = |bound: Option<Level>, orderings: &[std::cmp::Ordering]| {
+                    bound.is_none_or(|bound| {
+                        x.level
+                            .and_then(|lv| compare_level(lv, bound))
+                            .is_some_and(|x| orderings.contains(&x))
+                    })
+                };
+
+                self.difficulty.is_none_or(|diff| x.difficulty == diff)
+                    && level_is(self.level, &[Equal])
+                    && level_is(self.min_level, &[Equal, Greater])
+                    && level_is(self.max_level, &[Less, Equal])
+            })
+    }
+}
+
+/// Compares a chart's level against a level given in a query, or returns `None` if special
+/// levels are involved and they are not the same.
+fn compare_level(level: Level, bound: Level) -> Option<std::cmp::Ordering> {
+    match (level, bound) {
+        (Level::Char(a), Level::Char(b)) if a == b => Some(std::cmp::Ordering::Equal),
+        (Level::Char(_), _) | (_, Level::Char(_)) => None,
+        // chart constants fall into the "Lv.X" or "Lv.X+" level they are shown as
+        (level, bound @ (Level::Normal(_) | Level::Plus(_))) => Some(level.bucket().cmp(&bound)),
+        (level, bound) => Some(level.cmp(&bound)),
+    }
+}
+
+fn song_to_json(x: &SongRecord) -> Json {
+    Json::object(vec![
+        ("path", Json::from(x.path.to_string_lossy().into_owned())),
+        ("mtime", Json::Number(x.mtime as f64)),
+        ("title", Json::from(x.title.as_str())),
+        ("artist", Json::from(x.artist.as_str())),
+        (
+            "min_bpm",
+            opt_to_json(x.min_bpm.map(|x| Json::Number(x as f64))),
+        ),
+        (
+            "max_bpm",
+            opt_to_json(x.max_bpm.map(|x| Json::Number(x as f64))),
+        ),
+        (
+            "charts",
+            x.charts
+                .iter()
+                .map(chart_to_json)
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+    ])
+}
+
+fn song_from_json(x: &Json) -> Option<SongRecord> {
+    Some(SongRecord {
+        path: PathBuf::from(x.get("path")?.as_str()?),
+        mtime: x.get("mtime")?.as_usize()? as u64,
+        title: x.get("title")?.as_str()?.to_owned(),
+        artist: x.get("artist")?.as_str()?.to_owned(),
+        min_bpm: opt_from_json(x.get("min_bpm")?, as_f32)?,
+        max_bpm: opt_from_json(x.get("max_bpm")?, as_f32)?,
+        charts: x
+            .get("charts")?
+            .as_array()?
+            .iter()
+            .map(chart_from_json)
+            .collect::<Option<_>>()?,
+    })
+}
+
+fn chart_to_json(x: &ChartRecord) -> Json {
+    Json::object(vec![
+        ("difficulty", Json::from(x.difficulty.index() as usize)),
+        (
+            "level",
+            opt_to_json(x.level.map(|x| Json::from(x.to_string()))),
+        ),
+        (
+            "raw_level",
+            opt_to_json(x.raw_level.as_deref().map(Json::from)),
+        ),
+        (
+            "designer",
+            opt_to_json(x.designer.as_deref().map(Json::from)),
+        ),
+        ("taps", Json::from(x.num_taps as usize)),
+        ("holds", Json::from(x.num_holds as usize)),
+        ("slides", Json::from(x.num_slides as usize)),
+        ("breaks", Json::from(x.num_breaks as usize)),
+    ])
+}
+
+fn chart_from_json(x: &Json) -> Option<ChartRecord> {
+    use std::convert::TryInto;
+
+    let count = |key: &str| x.get(key)?.as_usize().map(|x| x as u32);
+
+    Some(ChartRecord {
+        difficulty: maidata::Difficulty::from_index(
+            x.get("difficulty")?.as_usize()?.try_into().ok()?,
+        ),
+        level: opt_from_json(x.get("level")?, |x| x.as_str()?.parse().ok())?,
+        raw_level: opt_from_json(x.get("raw_level")?, |x| x.as_str().map(str::to_owned))?,
+        designer: opt_from_json(x.get("designer")?, |x| x.as_str().map(str::to_owned))?,
+        num_taps: count("taps")?,
+        num_holds: count("holds")?,
+        num_slides: count("slides")?,
+        num_breaks: count("breaks")?,
+    })
+}
+
+fn opt_to_json(x: Option<Json>) -> Json {
+    x.unwrap_or(Json::Null)
+}
+
+/// Converts a nullable value, returning `None` if it is neither null nor convertible.
+fn opt_from_json<T, F: Fn(&Json) -> Option<T>>(x: &Json, f: F) -> Option<Option<T>> {
+    match x {
+        Json::Null => Some(None),
+        x => f(x).map(Some),
+    }
+}
+
+fn as_f32(x: &Json) -> Option<f32> {
+    match x {
+        Json::Number(x) => Some(*x as f32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maidata::Difficulty;
+
+    fn song() -> SongRecord {
+        SongRecord {
+            path: PathBuf::from("songs/foo"),
+            mtime: 1_600_000_000_123,
+            title: "Foo \"Bar\"".to_owned(),
+            artist: "baz".to_owned(),
+            min_bpm: Some(150.0),
+            max_bpm: Some(187.5),
+            charts: vec![
+                ChartRecord {
+                    difficulty: Difficulty::Expert,
+                    level: Some(Level::Plus(10)),
+                    raw_level: Some("10+".to_owned()),
+                    designer: None,
+                    num_taps: 300,
+                    num_holds: 20,
+                    num_slides: 30,
+                    num_breaks: 5,
+                },
+                ChartRecord {
+                    difficulty: Difficulty::Master,
+                    level: Some(Level::Constant(13.7)),
+                    raw_level: Some("13.7".to_owned()),
+                    designer: Some("someone".to_owned()),
+                    num_taps: 600,
+                    num_holds: 40,
+                    num_slides: 60,
+                    num_breaks: 15,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let index = Index {
+            songs: vec![song()],
+        };
+        let x = json::parse(&index.to_json().to_string()).unwrap();
+        assert_eq!(Index::from_json(&x), Some(index));
+    }
+
+    #[test]
+    fn test_query() {
+        let song = song();
+        let query = |f: fn(&mut Query)| {
+            let mut q = Query::default();
+            f(&mut q);
+            q.matches(&song)
+        };
+
+        assert!(query(|_| {}));
+        assert!(query(|q| q.title = Some("foo \"b".to_owned())));
+        assert!(!query(|q| q.artist = Some("qux".to_owned())));
+        assert!(query(|q| q.designer = Some("SOME".to_owned())));
+        assert!(query(|q| {
+            q.difficulty = Some(Difficulty::Master);
+            q.level = Some(Level::Plus(13));
+        }));
+        // the level belongs to another chart
+        assert!(!query(|q| {
+            q.difficulty = Some(Difficulty::Expert);
+            q.level = Some(Level::Plus(13));
+        }));
+        assert!(!query(|q| q.level = Some(Level::Normal(13))));
+        assert!(query(|q| q.level = Some(Level::Constant(13.7))));
+        assert!(!query(|q| q.level = Some(Level::Char('宴'))));
+    }
+
+    #[test]
+    fn test_query_level_range() {
+        let song = song();
+        let query = |min: Option<&str>, max: Option<&str>| {
+            let q = Query {
+                min_level: min.map(|x| x.parse().unwrap()),
+                max_level: max.map(|x| x.parse().unwrap()),
+                ..Query::default()
+            };
+            q.matches(&song)
+        };
+
+        assert!(query(Some("13+"), None));
+        assert!(query(Some("13.5"), Some("13.7")));
+        assert!(query(None, Some("11")));
+        assert!(!query(Some("14"), None));
+        assert!(!query(Some("11"), Some("13")));
+        assert!(!query(Some("13.8"), None));
+    }
+}
@@ -0,0 +1,102 @@
+mod index;
+mod scan;
+
+use std::path::PathBuf;
+
+use index::{Index, Query};
+
+const USAGE: &str = "usage:
+    $0 scan <index.json> <dir>...
+    $0 query <index.json> [--title <text>] [--artist <text>] [--designer <text>]
+        [--difficulty <difficulty>] [--level <level>] [--min-level <level>]
+        [--max-level <level>]";
+
+fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [cmd, index_path, dirs @ ..] if cmd == "scan" && !dirs.is_empty() => scan(index_path, dirs),
+        [cmd, index_path, filters @ ..] if cmd == "query" => query(index_path, filters),
+        _ => panic!("{}", USAGE),
+    }
+}
+
+fn scan(index_path: &str, dirs: &[String]) {
+    let old = load_index(index_path);
+    let roots: Vec<_> = dirs.iter().map(PathBuf::from).collect();
+    let (index, report) = scan::scan(&roots, &old);
+
+    for (path, e) in &report.errors {
+        eprintln!("{}: {}", path.display(), e);
+    }
+    println!(
+        "{} songs: {} added, {} updated, {} unchanged, {} failed",
+        index.songs.len(),
+        report.num_added,
+        report.num_updated,
+        report.num_unchanged,
+        report.errors.len(),
+    );
+
+    if let Err(e) = index.save(index_path) {
+        panic!("saving index failed: {}", e);
+    }
+}
+
+fn query(index_path: &str, filters: &[String]) {
+    let mut query = Query::default();
+    for filter in filters.chunks(2) {
+        let value = match filter {
+            [_, value] => value.clone(),
+            _ => panic!("{}", USAGE),
+        };
+
+        match filter[0].as_str() {
+            "--title" => query.title = Some(value),
+            "--artist" => query.artist = Some(value),
+            "--designer" => query.designer = Some(value),
+            "--difficulty" => {
+                query.difficulty = Some(value.parse().unwrap_or_else(|_| panic!("{}", USAGE)))
+            }
+            "--level" => query.level = Some(parse_level(&value)),
+            "--min-level" => query.min_level = Some(parse_level(&value)),
+            "--max-level" => query.max_level = Some(parse_level(&value)),
+            _ => panic!("{}", USAGE),
+        }
+    }
+
+    let index = load_index(index_path);
+    for song in index.query(&query) {
+        let bpm = match (song.min_bpm, song.max_bpm) {
+            (Some(min), Some(max)) if min != max => format!("{}-{}", min, max),
+            (Some(bpm), _) => bpm.to_string(),
+            _ => "?".to_owned(),
+        };
+        let charts: Vec<_> = song
+            .charts
+            .iter()
+            .map(|x| {
+                format!(
+                    "{} {} ({} notes)",
+                    x.difficulty,
+                    x.raw_level.as_deref().unwrap_or("?"),
+                    x.total_notes()
+                )
+            })
+            .collect();
+
+        println!("{} / {}", song.title, song.artist);
+        println!("    BPM {}; {}", bpm, charts.join(", "));
+        println!("    {}", song.path.display());
+    }
+}
+
+fn parse_level(x: &str) -> maidata::Level {
+    x.parse().unwrap_or_else(|_| panic!("{}", USAGE))
+}
+
+fn load_index(path: &str) -> Index {
+    match Index::load(path) {
+        Ok(x) => x,
+        Err(e) => panic!("loading index failed: {}", e),
+    }
+}
@@ -0,0 +1,188 @@
+//! Finding songs on disk and summarizing them into index records.
+
+use std::path::{Path, PathBuf};
+
+use maidata::container::Maidata;
+use maidata::insn::RawInsn;
+use maidata::materialize::{ChartStats, MaterializationContext};
+use maidata::package::SongPackage;
+
+use crate::index::{ChartRecord, Index, SongRecord};
+
+/// What happened during a scan.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub num_added: usize,
+    pub num_updated: usize,
+    pub num_unchanged: usize,
+    /// Songs that could not be loaded, with the reason.
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+/// Builds the index of all songs under `roots`, reusing records of `old` for songs whose
+/// `maidata.txt` has not been modified since.
+///
+/// Songs of `old` no longer found are not carried over.
+pub fn scan(roots: &[PathBuf], old: &Index) -> (Index, ScanReport) {
+    let mut found = vec![];
+    let mut report = ScanReport::default();
+    for root in roots {
+        if let Err(e) = find_songs(root, &mut found) {
+            report.errors.push((root.clone(), e.to_string()));
+        }
+    }
+
+    let mut index = Index::default();
+    for (dir, mtime) in found {
+        let is_known = match old.find(&dir) {
+            Some(record) if record.mtime == mtime => {
+                index.songs.push(record.clone());
+                report.num_unchanged += 1;
+                continue;
+            }
+            x => x.is_some(),
+        };
+
+        match SongPackage::from_dir(&dir) {
+            Ok(pkg) => {
+                index.songs.push(summarize(dir, mtime, pkg.maidata()));
+                if is_known {
+                    report.num_updated += 1;
+                } else {
+                    report.num_added += 1;
+                }
+            }
+            Err(e) => report.errors.push((dir, e.to_string())),
+        }
+    }
+
+    (index, report)
+}
+
+/// Collects directories holding `maidata.txt` under `dir`, along with its modification time.
+fn find_songs(dir: &Path, out: &mut Vec<(PathBuf, u64)>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|x| x.file_name());
+
+    for entry in entries {
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            find_songs(&entry.path(), out)?;
+        } else if file_type.is_file()
+            && entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case("maidata.txt")
+        {
+            let mtime = entry
+                .metadata()?
+                .modified()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |x| x.as_millis() as u64);
+            out.push((dir.to_owned(), mtime));
+        }
+    }
+
+    Ok(())
+}
+
+fn summarize(path: PathBuf, mtime: u64, maidata: &Maidata) -> SongRecord {
+    let mut bpms = vec![];
+    let mut charts: Vec<_> = maidata
+        .iter_difficulties()
+        .map(|diff| {
+            bpms.extend(diff.iter_insns().filter_map(|x| match &**x {
+                RawInsn::Bpm(params) => Some(params.new_bpm),
+                _ => None,
+            }));
+
+            let mut mcx = MaterializationContext::with_offset(0.0);
+            let stats = ChartStats::from_notes(&mcx.materialize_insns(diff.iter_insns()));
+            ChartRecord {
+                difficulty: diff.difficulty(),
+                level: diff.level(),
+                raw_level: diff.raw_level().map(str::to_owned),
+                designer: diff.designer().map(str::to_owned),
+                num_taps: stats.num_taps,
+                num_holds: stats.num_holds,
+                num_slides: stats.num_slides,
+                num_breaks: stats.num_breaks,
+            }
+        })
+        .collect();
+    charts.sort_by_key(|x| x.difficulty);
+
+    // charts without tempo changes have no BPM to tell
+    if bpms.is_empty() {
+        bpms.extend(maidata.star_bpm());
+    }
+
+    SongRecord {
+        path,
+        mtime,
+        title: maidata.title().to_owned(),
+        artist: maidata.artist().to_owned(),
+        min_bpm: bpms.iter().copied().reduce(f32::min),
+        max_bpm: bpms.iter().copied().reduce(f32::max),
+        charts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan() {
+        let root = std::env::temp_dir().join(format!("maidata-library-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let song_dir = root.join("pack").join("song");
+        std::fs::create_dir_all(&song_dir).unwrap();
+        std::fs::create_dir_all(root.join("broken")).unwrap();
+        std::fs::write(
+            song_dir.join("maidata.txt"),
+            "&title=foo\n&artist=bar\n&des_5=baz\n&lv_5=13+\n\
+             &inote_5=(150){4}1,2b,3h[4:1],(180)4-6[4:1],E\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("broken").join("MAIDATA.TXT"), "title=foo").unwrap();
+
+        let roots = vec![root.clone()];
+        let (index, report) = scan(&roots, &Index::default());
+        assert_eq!((report.num_added, report.errors.len()), (1, 1));
+        assert_eq!(report.errors[0].0, root.join("broken"));
+
+        assert_eq!(index.songs.len(), 1);
+        let song = &index.songs[0];
+        assert_eq!(song.path, song_dir);
+        assert_eq!((song.min_bpm, song.max_bpm), (Some(150.0), Some(180.0)));
+        assert_eq!(song.charts.len(), 1);
+        let chart = &song.charts[0];
+        assert_eq!(chart.difficulty, maidata::Difficulty::Master);
+        assert_eq!(chart.level, Some(maidata::Level::Plus(13)));
+        assert_eq!(chart.designer.as_deref(), Some("baz"));
+        assert_eq!(
+            (
+                chart.num_taps,
+                chart.num_breaks,
+                chart.num_holds,
+                chart.num_slides
+            ),
+            (2, 1, 1, 1)
+        );
+
+        // unmodified songs are taken from the old index
+        let mut old = index.clone();
+        old.songs[0].title = "cached".to_owned();
+        let (index, report) = scan(&roots, &old);
+        assert_eq!((report.num_unchanged, report.num_added), (1, 0));
+        assert_eq!(index.songs[0].title, "cached");
+
+        old.songs[0].mtime -= 1;
+        let (index, report) = scan(&roots, &old);
+        assert_eq!(report.num_updated, 1);
+        assert_eq!(index.songs[0].title, "foo");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+//! Per-document analysis results, in terms of byte offsets into the document.
+
+use maidata::container::Entry;
+use maidata::insn::{Cst, RawInsn, SpRawInsn};
+use maidata::lint::{LintConfig, Severity};
+use maidata::materialize::MaterializationContext;
+
+pub struct Diagnostic {
+    pub range: std::ops::Range<usize>,
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+}
+
+/// Where an instruction lies on the timeline.
+#[derive(Copy, Clone, Debug)]
+pub struct Timing {
+    pub ts: f32,
+    /// Number of beats elapsed since the start of chart.
+    pub beats: f32,
+    pub bpm: Option<f32>,
+    /// Range of the `(bpm)` instruction in effect.
+    pub bpm_range: Option<(usize, usize)>,
+}
+
+impl Timing {
+    /// 1-based measure number, assuming 4 beats per measure.
+    pub fn measure(&self) -> u32 {
+        (self.beats / 4.0).floor() as u32 + 1
+    }
+
+    /// 1-based beat number in the measure.
+    pub fn beat_in_measure(&self) -> f32 {
+        self.beats % 4.0 + 1.0
+    }
+}
+
+pub struct Chart {
+    /// Key of the entry, e.g. `inote_5`.
+    pub name: String,
+    pub key_range: std::ops::Range<usize>,
+    pub value_range: std::ops::Range<usize>,
+    /// Instructions with spans relative to the start of value.
+    pub insns: Vec<SpRawInsn>,
+    pub timings: Vec<Timing>,
+    pub parse_errors: Vec<std::ops::Range<usize>>,
+    cst: Cst,
+}
+
+impl Chart {
+    fn new(entry: &Entry, offset: f32) -> Self {
+        let base = entry.value.span().byte_offset;
+        let cst = Cst::parse(&entry.value);
+
+        let insns: Vec<_> = cst
+            .insns()
+            .iter()
+            .filter_map(|x| x.raw().cloned())
+            .collect();
+        let parse_errors = match cst.lower() {
+            Ok(_) => vec![],
+            Err(spans) => spans
+                .into_iter()
+                .map(|x| base + x.byte_offset..base + x.byte_offset + x.len)
+                .collect(),
+        };
+        let timings = compute_timings(&insns, offset, base);
+
+        Self {
+            name: (*entry.key).clone(),
+            key_range: range_of(entry.key.span()),
+            value_range: range_of(entry.value.span()),
+            insns,
+            timings,
+            parse_errors,
+            cst,
+        }
+    }
+
+    /// Returns the index of the top-level instruction at the offset.
+    pub fn insn_at(&self, offset: usize) -> Option<usize> {
+        let base = self.value_range.start;
+        self.insns.iter().position(|x| {
+            let span = x.span();
+            let start = base + span.byte_offset;
+            start <= offset && offset < start + span.len
+        })
+    }
+
+    pub fn insn_range(&self, idx: usize) -> std::ops::Range<usize> {
+        let span = self.insns[idx].span();
+        let start = self.value_range.start + span.byte_offset;
+        start..start + span.len
+    }
+
+    fn diagnostics(&self, out: &mut Vec<Diagnostic>) {
+        out.extend(self.parse_errors.iter().map(|range| Diagnostic {
+            range: range.clone(),
+            severity: Severity::Error,
+            code: None,
+            message: "malformed instruction".to_owned(),
+        }));
+        if !self.parse_errors.is_empty() {
+            // findings on a partial chart would be misleading
+            return;
+        }
+
+        let base = self.value_range.start;
+        for diag in maidata::lint::lint_insns(&self.insns, &LintConfig::default()) {
+            let span = diag.span();
+            out.push(Diagnostic {
+                range: base + span.byte_offset..base + span.byte_offset + span.len,
+                severity: diag.severity,
+                code: Some(diag.rule.id()),
+                message: diag.message.clone(),
+            });
+        }
+    }
+
+    /// Returns the chart with whitespace inside instructions removed, if it is free of syntax
+    /// errors. Comments and line breaks are kept.
+    pub fn formatted(&self) -> Option<String> {
+        if self.parse_errors.is_empty() {
+            Some(self.cst.format())
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Document {
+    pub text: String,
+    pub lines: LineIndex,
+    pub charts: Vec<Chart>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let lines = LineIndex::new(&text);
+        let mut diagnostics = vec![];
+        let mut charts = vec![];
+
+        match maidata::container::lex_entries(&text) {
+            Some(entries) => {
+                let offset_of = |key: &str| {
+                    entries
+                        .iter()
+                        .find(|x| *x.key == key)
+                        .and_then(|x| x.value.trim().parse::<f32>().ok())
+                };
+                let fallback_offset = offset_of("first");
+
+                for entry in &entries {
+                    let num = match entry.key.strip_prefix("inote_") {
+                        Some(x) => x,
+                        None => continue,
+                    };
+                    let offset = offset_of(&format!("first_{}", num))
+                        .or(fallback_offset)
+                        .unwrap_or(0.0);
+
+                    let chart = Chart::new(entry, offset);
+                    chart.diagnostics(&mut diagnostics);
+                    charts.push(chart);
+                }
+            }
+            None => diagnostics.push(Diagnostic {
+                range: 0..0,
+                severity: Severity::Error,
+                code: None,
+                message: "expected `&key=value` entries".to_owned(),
+            }),
+        }
+
+        Self {
+            text,
+            lines,
+            charts,
+            diagnostics,
+        }
+    }
+
+    pub fn chart_at(&self, offset: usize) -> Option<&Chart> {
+        self.charts
+            .iter()
+            .find(|x| x.value_range.start <= offset && offset <= x.value_range.end)
+    }
+}
+
+fn range_of(span: maidata::Span) -> std::ops::Range<usize> {
+    span.byte_offset..span.byte_offset + span.len
+}
+
+fn compute_timings(insns: &[SpRawInsn], offset: f32, base: usize) -> Vec<Timing> {
+    let mut mcx = MaterializationContext::with_offset(offset);
+    let mut curr = Timing {
+        ts: offset,
+        beats: 0.0,
+        bpm: None,
+        bpm_range: None,
+    };
+
+    insns
+        .iter()
+        .map(|insn| {
+            if let RawInsn::Bpm(p) = &**insn {
+                let span = insn.span();
+                curr.bpm = Some(p.new_bpm);
+                curr.bpm_range =
+                    Some((base + span.byte_offset, base + span.byte_offset + span.len));
+            }
+
+            curr.ts = mcx.curr_ts();
+            let result = curr;
+            let _ = mcx.materialize_raw_insn(insn);
+            if mcx.curr_beat_dur() > 0.0 {
+                curr.beats += (mcx.curr_ts() - result.ts) / mcx.curr_beat_dur();
+            }
+            result
+        })
+        .collect()
+}
+
+/// Converts between byte offsets and LSP positions, whose columns count UTF-16 code units.
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { line_starts }
+    }
+
+    pub fn position(&self, text: &str, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(x) => x,
+            Err(x) => x - 1,
+        };
+        let start = self.line_starts[line];
+        let col = text[start..offset].encode_utf16().count();
+        (line, col)
+    }
+
+    pub fn offset(&self, text: &str, line: usize, col: usize) -> usize {
+        let start = match self.line_starts.get(line) {
+            Some(x) => *x,
+            None => return text.len(),
+        };
+
+        let mut units = 0;
+        for (i, ch) in text[start..].char_indices() {
+            if units >= col || ch == '\n' {
+                return start + i;
+            }
+            units += ch.len_utf16();
+        }
+        text.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str =
+        "&title=テスト\n&first=1\n&inote_5=\n(120){4}1,2,\n(60)3h[4:1],1,\n&inote_6=1,2x,E\n";
+
+    #[test]
+    fn test_analysis() {
+        let doc = Document::new(SRC.to_owned());
+        assert_eq!(doc.charts.len(), 2);
+
+        let chart = &doc.charts[0];
+        let idx = chart.insn_at(SRC.find("3h").unwrap()).unwrap();
+        let timing = chart.timings[idx];
+        assert_eq!(timing.ts, 2.0);
+        assert_eq!((timing.measure(), timing.beat_in_measure()), (1, 3.0));
+        let (start, end) = timing.bpm_range.unwrap();
+        assert_eq!(&SRC[start..end], "(60)");
+        assert_eq!(chart.timings[idx + 1].beat_in_measure(), 3.5);
+
+        assert_eq!(doc.diagnostics.len(), 2);
+        let range = doc.diagnostics[1].range.clone();
+        assert_eq!(&SRC[range], "2x,");
+        assert!(doc.charts[1].formatted().is_none());
+    }
+
+    #[test]
+    fn test_formatted() {
+        let doc = Document::new("&inote_5=( 120 ){4}\n1 , 2h [4:1],|| intro\n3,E\n".to_owned());
+        assert_eq!(
+            doc.charts[0].formatted().unwrap(),
+            "(120){4}\n1, 2h[4:1],|| intro\n3,E"
+        );
+    }
+
+    #[test]
+    fn test_line_index() {
+        let lines = LineIndex::new(SRC);
+        let offset = SRC.find("&first").unwrap() - 1;
+        assert_eq!(lines.position(SRC, offset), (0, 10));
+        assert_eq!(lines.offset(SRC, 0, 10), offset);
+        assert_eq!(lines.offset(SRC, 0, 100), offset);
+        assert_eq!(lines.position(SRC, offset + 1), (1, 0));
+    }
+}
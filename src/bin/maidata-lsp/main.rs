@@ -0,0 +1,440 @@
+//! Language server for `maidata.txt`, speaking LSP over stdio.
+
+mod analysis;
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use analysis::Document;
+use maidata::json::{self, Json};
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+struct Server {
+    docs: HashMap<String, Document>,
+    is_shut_down: bool,
+}
+
+fn main() {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+
+    let code = match run(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("maidata-lsp: {}", e);
+            1
+        }
+    };
+    std::process::exit(code);
+}
+
+/// Serves messages until told to exit or the input ends, returning the exit code.
+fn run<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> std::io::Result<i32> {
+    let mut server = Server {
+        docs: HashMap::new(),
+        is_shut_down: false,
+    };
+
+    while let Some(msg) = read_message(input)? {
+        let msg = match msg {
+            Ok(x) => x,
+            Err(e) => {
+                write_message(output, &response(&Json::Null, Err((PARSE_ERROR, e))))?;
+                continue;
+            }
+        };
+
+        let id = msg.get("id");
+        let method = match msg.get("method").and_then(Json::as_str) {
+            Some(x) => x,
+            None => {
+                let result = Err((INVALID_REQUEST, "missing method".to_owned()));
+                write_message(output, &response(id.unwrap_or(&Json::Null), result))?;
+                continue;
+            }
+        };
+        if method == "exit" {
+            return Ok(if server.is_shut_down { 0 } else { 1 });
+        }
+
+        let params = msg.get("params").unwrap_or(&Json::Null);
+        let replies = match id {
+            Some(id) => {
+                let result = server.handle_request(method, params);
+                vec![response(id, result)]
+            }
+            None => server.handle_notification(method, params),
+        };
+
+        for reply in replies {
+            write_message(output, &reply)?;
+        }
+    }
+
+    Ok(0)
+}
+
+/// Reads one message, which is `Err` with the reason if it is not valid JSON. Returns `None` at
+/// the end of input.
+fn read_message<R: BufRead>(r: &mut R) -> std::io::Result<Option<Result<Json, String>>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(x) = line.strip_prefix("Content-Length:") {
+            len = x.trim().parse::<usize>().ok();
+        }
+    }
+
+    let len = match len {
+        Some(x) => x,
+        None => return Ok(Some(Err("missing Content-Length".to_owned()))),
+    };
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)?;
+
+    let result = String::from_utf8(body)
+        .map_err(|e| e.to_string())
+        .and_then(|x| json::parse(&x).map_err(|e| e.to_string()));
+    Ok(Some(result))
+}
+
+fn write_message<W: Write>(w: &mut W, msg: &Json) -> std::io::Result<()> {
+    let body = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()
+}
+
+fn response(id: &Json, result: Result<Json, (i32, String)>) -> Json {
+    let (key, value) = match result {
+        Ok(x) => ("result", x),
+        Err((code, message)) => (
+            "error",
+            Json::object(vec![
+                ("code", Json::Number(code as f64)),
+                ("message", message.into()),
+            ]),
+        ),
+    };
+
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        (key, value),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+impl Server {
+    fn handle_request(&mut self, method: &str, params: &Json) -> Result<Json, (i32, String)> {
+        match method {
+            "initialize" => Ok(Json::object(vec![
+                (
+                    "capabilities",
+                    Json::object(vec![
+                        // full document sync
+                        ("textDocumentSync", 1.into()),
+                        ("hoverProvider", true.into()),
+                        ("definitionProvider", true.into()),
+                        ("documentSymbolProvider", true.into()),
+                        ("documentFormattingProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object(vec![("name", "maidata-lsp".into())]),
+                ),
+            ])),
+            "shutdown" => {
+                self.is_shut_down = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => self.with_position(params, hover),
+            "textDocument/definition" => self.with_position(params, definition),
+            "textDocument/documentSymbol" => {
+                let (_, doc) = self.doc_of(params)?;
+                Ok(document_symbols(doc))
+            }
+            "textDocument/formatting" => {
+                let (_, doc) = self.doc_of(params)?;
+                Ok(formatting(doc))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method {}", method))),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = match params.path(&["textDocument", "uri"]).and_then(Json::as_str) {
+            Some(x) => x.to_owned(),
+            None => return vec![],
+        };
+
+        let text = match method {
+            "textDocument/didOpen" => params.path(&["textDocument", "text"]),
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(|x| x.last())
+                .and_then(|x| x.get("text")),
+            "textDocument/didClose" => {
+                self.docs.remove(&uri);
+                // clear the diagnostics
+                let params = Json::object(vec![
+                    ("uri", uri.into()),
+                    ("diagnostics", Json::Array(vec![])),
+                ]);
+                return vec![notification("textDocument/publishDiagnostics", params)];
+            }
+            _ => None,
+        };
+
+        match text.and_then(Json::as_str) {
+            Some(text) => {
+                let doc = Document::new(text.to_owned());
+                let msg = publish_diagnostics(&uri, &doc);
+                self.docs.insert(uri, doc);
+                vec![msg]
+            }
+            None => vec![],
+        }
+    }
+
+    fn doc_of<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), (i32, String)> {
+        let uri = params
+            .path(&["textDocument", "uri"])
+            .and_then(Json::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, "missing document URI".to_owned()))?;
+        let doc = self
+            .docs
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("document {} is not open", uri)))?;
+        Ok((uri, doc))
+    }
+
+    fn with_position<F: Fn(&str, &Document, usize) -> Json>(
+        &self,
+        params: &Json,
+        f: F,
+    ) -> Result<Json, (i32, String)> {
+        let (uri, doc) = self.doc_of(params)?;
+        let line = params.path(&["position", "line"]).and_then(Json::as_usize);
+        let col = params
+            .path(&["position", "character"])
+            .and_then(Json::as_usize);
+        match (line, col) {
+            (Some(line), Some(col)) => Ok(f(uri, doc, doc.lines.offset(&doc.text, line, col))),
+            _ => Err((INVALID_PARAMS, "missing position".to_owned())),
+        }
+    }
+}
+
+fn position(doc: &Document, offset: usize) -> Json {
+    let (line, col) = doc.lines.position(&doc.text, offset);
+    Json::object(vec![("line", line.into()), ("character", col.into())])
+}
+
+fn range(doc: &Document, range: std::ops::Range<usize>) -> Json {
+    Json::object(vec![
+        ("start", position(doc, range.start)),
+        ("end", position(doc, range.end)),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, doc: &Document) -> Json {
+    use maidata::lint::Severity;
+
+    let diagnostics = doc
+        .diagnostics
+        .iter()
+        .map(|diag| {
+            let severity = match diag.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            };
+            let mut fields = vec![
+                ("range", range(doc, diag.range.clone())),
+                ("severity", Json::from(severity as usize)),
+                ("source", "maidata".into()),
+                ("message", diag.message.as_str().into()),
+            ];
+            if let Some(code) = diag.code {
+                fields.push(("code", code.into()));
+            }
+            Json::object(fields)
+        })
+        .collect();
+
+    let params = Json::object(vec![
+        ("uri", uri.into()),
+        ("diagnostics", Json::Array(diagnostics)),
+    ]);
+    notification("textDocument/publishDiagnostics", params)
+}
+
+fn hover(_: &str, doc: &Document, offset: usize) -> Json {
+    let chart = match doc.chart_at(offset) {
+        Some(x) => x,
+        None => return Json::Null,
+    };
+    let idx = match chart.insn_at(offset) {
+        Some(x) => x,
+        None => return Json::Null,
+    };
+
+    let timing = chart.timings[idx];
+    let mut text = format!(
+        "**{:.3}s**, measure {} beat {}",
+        timing.ts,
+        timing.measure(),
+        (timing.beat_in_measure() * 1000.0).round() / 1000.0,
+    );
+    if let Some(bpm) = timing.bpm {
+        text += &format!(" (BPM {})", bpm);
+    }
+
+    Json::object(vec![
+        (
+            "contents",
+            Json::object(vec![("kind", "markdown".into()), ("value", text.into())]),
+        ),
+        ("range", range(doc, chart.insn_range(idx))),
+    ])
+}
+
+/// Goes to the BPM change in effect at the instruction.
+fn definition(uri: &str, doc: &Document, offset: usize) -> Json {
+    let bpm_range = doc
+        .chart_at(offset)
+        .and_then(|chart| Some(chart.timings[chart.insn_at(offset)?]))
+        .and_then(|timing| timing.bpm_range);
+
+    match bpm_range {
+        Some((start, end)) => {
+            Json::object(vec![("uri", uri.into()), ("range", range(doc, start..end))])
+        }
+        None => Json::Null,
+    }
+}
+
+fn document_symbols(doc: &Document) -> Json {
+    // SymbolKind values from the LSP specification
+    const MODULE: usize = 2;
+    const EVENT: usize = 24;
+
+    let symbols = doc
+        .charts
+        .iter()
+        .map(|chart| {
+            let mut bpm_changes: Vec<(f32, (usize, usize))> = vec![];
+            for timing in &chart.timings {
+                if let (Some(bpm), Some(r)) = (timing.bpm, timing.bpm_range) {
+                    if bpm_changes.last().map(|x| x.1) != Some(r) {
+                        bpm_changes.push((bpm, r));
+                    }
+                }
+            }
+
+            let bpm_changes = bpm_changes
+                .into_iter()
+                .map(|(bpm, (start, end))| {
+                    Json::object(vec![
+                        ("name", format!("({})", bpm).into()),
+                        ("kind", EVENT.into()),
+                        ("range", range(doc, start..end)),
+                        ("selectionRange", range(doc, start..end)),
+                    ])
+                })
+                .collect();
+
+            let whole = chart.key_range.start..chart.value_range.end;
+            Json::object(vec![
+                ("name", chart.name.as_str().into()),
+                ("kind", MODULE.into()),
+                ("range", range(doc, whole)),
+                ("selectionRange", range(doc, chart.key_range.clone())),
+                ("children", Json::Array(bpm_changes)),
+            ])
+        })
+        .collect();
+
+    Json::Array(symbols)
+}
+
+fn formatting(doc: &Document) -> Json {
+    let edits = doc
+        .charts
+        .iter()
+        .filter_map(|chart| {
+            let new_text = chart.formatted()?;
+            if doc.text[chart.value_range.clone()] == new_text {
+                return None;
+            }
+
+            Some(Json::object(vec![
+                ("range", range(doc, chart.value_range.clone())),
+                ("newText", new_text.into()),
+            ]))
+        })
+        .collect();
+
+    Json::Array(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn test_bad_messages_get_error_responses() {
+        let input = [
+            frame("{\"jsonrpc\":"),
+            frame(r#"{"jsonrpc":"2.0","id":1}"#),
+            frame(r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#),
+            frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+        ]
+        .concat();
+        let mut output = vec![];
+        let code = run(&mut input.as_bytes(), &mut output).unwrap();
+        assert_eq!(code, 0);
+
+        let mut output = output.as_slice();
+        let mut next = || read_message(&mut output).unwrap().unwrap().unwrap();
+        let error = |x: &Json| (x.get("id").cloned(), x.path(&["error", "code"]).cloned());
+
+        assert_eq!(
+            error(&next()),
+            (Some(Json::Null), Some(Json::Number(PARSE_ERROR as f64)))
+        );
+        assert_eq!(
+            error(&next()),
+            (
+                Some(Json::Number(1.0)),
+                Some(Json::Number(INVALID_REQUEST as f64))
+            )
+        );
+        assert_eq!(next().get("id"), Some(&Json::Number(2.0)));
+        assert!(read_message(&mut output).unwrap().is_none());
+    }
+}
@@ -0,0 +1,312 @@
+/// Text encodings `maidata.txt` is commonly found in.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Shift_JIS, or rather Microsoft's code page 932, as used by older Japanese charts.
+    ShiftJis,
+    /// GBK, as used by older Chinese charts.
+    Gbk,
+}
+
+impl TextEncoding {
+    pub const ALL: &'static [TextEncoding] = &[
+        TextEncoding::Utf8,
+        TextEncoding::Utf16Le,
+        TextEncoding::Utf16Be,
+        TextEncoding::ShiftJis,
+        TextEncoding::Gbk,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16Le => "UTF-16LE",
+            TextEncoding::Utf16Be => "UTF-16BE",
+            TextEncoding::ShiftJis => "Shift_JIS",
+            TextEncoding::Gbk => "GBK",
+        }
+    }
+
+    fn to_encoding_rs(self) -> &'static encoding_rs::Encoding {
+        match self {
+            TextEncoding::Utf8 => encoding_rs::UTF_8,
+            TextEncoding::Utf16Le => encoding_rs::UTF_16LE,
+            TextEncoding::Utf16Be => encoding_rs::UTF_16BE,
+            TextEncoding::ShiftJis => encoding_rs::SHIFT_JIS,
+            TextEncoding::Gbk => encoding_rs::GBK,
+        }
+    }
+}
+
+impl std::fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TextEncodingParseError {
+    UnknownEncoding(String),
+}
+
+impl std::str::FromStr for TextEncoding {
+    type Err = TextEncodingParseError;
+
+    /// Accepts the names given by [`TextEncoding::name`] case-insensitively, ignoring `-` and
+    /// `_`, so `utf8` and `shiftjis` work too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalize = |x: &str| -> String {
+            x.chars()
+                .filter(|ch| *ch != '-' && *ch != '_')
+                .flat_map(char::to_lowercase)
+                .collect()
+        };
+        let wanted = normalize(s);
+
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|x| normalize(x.name()) == wanted)
+            .ok_or_else(|| TextEncodingParseError::UnknownEncoding(s.to_owned()))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DecodeError {
+    /// The content is not valid in the given encoding, either requested explicitly or indicated
+    /// by a BOM.
+    Malformed(TextEncoding),
+    /// The content is not valid in any of the supported encodings.
+    UnknownEncoding,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Malformed(encoding) => write!(f, "content is not valid {}", encoding),
+            DecodeError::UnknownEncoding => write!(f, "cannot detect encoding of content"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes the raw content of `maidata.txt`, returning the text along with the encoding used.
+///
+/// If `encoding` is `None`, the encoding is detected: BOMs are honored, then UTF-8 and BOM-less
+/// UTF-16 are tried, and finally whichever of Shift_JIS and GBK gives the more plausible text.
+/// Any BOM is stripped.
+pub fn decode(
+    bytes: &[u8],
+    encoding: Option<TextEncoding>,
+) -> Result<(String, TextEncoding), DecodeError> {
+    let (bom_encoding, bom_len) = match sniff_bom(bytes) {
+        Some((x, len)) => (Some(x), len),
+        None => (None, 0),
+    };
+
+    let encoding = match encoding.or(bom_encoding) {
+        Some(x) => x,
+        None => return detect(bytes).ok_or(DecodeError::UnknownEncoding),
+    };
+
+    // only strip the BOM if it is of the encoding in use
+    let bytes = if bom_encoding == Some(encoding) {
+        &bytes[bom_len..]
+    } else {
+        bytes
+    };
+    decode_strict(bytes, encoding)
+        .map(|x| (x, encoding))
+        .ok_or(DecodeError::Malformed(encoding))
+}
+
+fn sniff_bom(bytes: &[u8]) -> Option<(TextEncoding, usize)> {
+    match bytes {
+        [0xef, 0xbb, 0xbf, ..] => Some((TextEncoding::Utf8, 3)),
+        [0xff, 0xfe, ..] => Some((TextEncoding::Utf16Le, 2)),
+        [0xfe, 0xff, ..] => Some((TextEncoding::Utf16Be, 2)),
+        _ => None,
+    }
+}
+
+fn decode_strict(bytes: &[u8], encoding: TextEncoding) -> Option<String> {
+    encoding
+        .to_encoding_rs()
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|x| x.into_owned())
+}
+
+fn detect(bytes: &[u8]) -> Option<(String, TextEncoding)> {
+    // ASCII-only UTF-16 is valid UTF-8 as well, NUL being a legal character, so it goes first
+    if let Some(encoding) = guess_utf16(bytes) {
+        if let Some(x) = decode_strict(bytes, encoding) {
+            return Some((x, encoding));
+        }
+    }
+
+    if let Some(x) = decode_strict(bytes, TextEncoding::Utf8) {
+        return Some((x, TextEncoding::Utf8));
+    }
+
+    // Shift_JIS comes first so it wins ties, being the more common of the two
+    [TextEncoding::ShiftJis, TextEncoding::Gbk]
+        .iter()
+        .filter_map(|&encoding| decode_strict(bytes, encoding).map(|x| (x, encoding)))
+        .fold(
+            None,
+            |best: Option<(String, TextEncoding, i64)>, (x, encoding)| {
+                let score = plausibility(&x);
+                match best {
+                    Some(best) if best.2 >= score => Some(best),
+                    _ => Some((x, encoding, score)),
+                }
+            },
+        )
+        .map(|(x, encoding, _)| (x, encoding))
+}
+
+/// BOM-less UTF-16 is recognized by its abundance of NUL bytes, as `maidata.txt` is mostly ASCII.
+fn guess_utf16(bytes: &[u8]) -> Option<TextEncoding> {
+    if bytes.len() < 2 || bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let num_units = bytes.len() / 2;
+    let num_zeros_at = |parity: usize| {
+        bytes
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|x| **x == 0)
+            .count()
+    };
+
+    if num_zeros_at(1) * 2 > num_units {
+        Some(TextEncoding::Utf16Le)
+    } else if num_zeros_at(0) * 2 > num_units {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Scores how likely `x` is meaningful CJK text, rather than a misinterpretation of bytes.
+fn plausibility(x: &str) -> i64 {
+    x.chars()
+        .map(|ch| match ch {
+            // hiragana and katakana, seldom found in GBK text read as Shift_JIS
+            '\u{3040}'..='\u{30ff}' => 2,
+            // CJK unified ideographs
+            '\u{4e00}'..='\u{9fff}' => 1,
+            // halfwidth katakana, what GBK text read as Shift_JIS is mostly made of
+            '\u{ff61}'..='\u{ff9f}' => -2,
+            // ASCII, CJK punctuation and fullwidth forms
+            '\0'..='\u{7f}' | '\u{3000}'..='\u{303f}' | '\u{ff00}'..='\u{ff60}' => 0,
+            _ => -1,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(x: &str, encoding: TextEncoding) -> Vec<u8> {
+        match encoding {
+            // encoding_rs only encodes into ASCII-compatible encodings
+            TextEncoding::Utf16Le => x.encode_utf16().flat_map(|u| u.to_le_bytes()).collect(),
+            TextEncoding::Utf16Be => x.encode_utf16().flat_map(|u| u.to_be_bytes()).collect(),
+            _ => {
+                let (bytes, _, had_errors) = encoding.to_encoding_rs().encode(x);
+                assert!(!had_errors);
+                bytes.into_owned()
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        const JA: &str = "&title=ぼくらの16bit戦争\n&artist=sasakure.UK\n&inote_5=(150){4}1,E\n";
+        const ZH: &str = "&title=极乐净土\n&des=谱面制作者\n&inote_5=(120){4}1,E\n";
+
+        for &(text, encoding) in &[
+            (JA, TextEncoding::Utf8),
+            (JA, TextEncoding::Utf16Le),
+            (JA, TextEncoding::Utf16Be),
+            (JA, TextEncoding::ShiftJis),
+            (ZH, TextEncoding::Gbk),
+        ] {
+            let bytes = encode(text, encoding);
+            assert_eq!(
+                decode(&bytes, None),
+                Ok((text.to_owned(), encoding)),
+                "{}",
+                encoding
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_ascii_utf16() {
+        const ASCII: &str = "&title=foo\n&inote_5=(120){4}1,E\n";
+
+        for &encoding in &[TextEncoding::Utf16Le, TextEncoding::Utf16Be] {
+            let bytes = encode(ASCII, encoding);
+            assert_eq!(
+                decode(&bytes, None),
+                Ok((ASCII.to_owned(), encoding)),
+                "{}",
+                encoding
+            );
+        }
+    }
+
+    #[test]
+    fn test_bom() {
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(encode("&title=曲", TextEncoding::Utf16Le));
+        assert_eq!(
+            decode(&bytes, None),
+            Ok(("&title=曲".to_owned(), TextEncoding::Utf16Le))
+        );
+
+        let bytes = b"\xef\xbb\xbf&title=x";
+        assert_eq!(
+            decode(bytes, None),
+            Ok(("&title=x".to_owned(), TextEncoding::Utf8))
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        let bytes = encode("&title=曲", TextEncoding::Gbk);
+        assert_eq!(
+            decode(&bytes, Some(TextEncoding::Gbk)),
+            Ok(("&title=曲".to_owned(), TextEncoding::Gbk))
+        );
+        assert_eq!(
+            decode(&[0x81, 0x20], Some(TextEncoding::Utf8)),
+            Err(DecodeError::Malformed(TextEncoding::Utf8))
+        );
+        assert_eq!(
+            decode(&[0xff, 0xff, 0xff], None),
+            Err(DecodeError::UnknownEncoding)
+        );
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!(
+            "shift-jis".parse::<TextEncoding>().ok(),
+            Some(TextEncoding::ShiftJis)
+        );
+        assert_eq!(
+            "UTF16LE".parse::<TextEncoding>().ok(),
+            Some(TextEncoding::Utf16Le)
+        );
+        assert!("latin1".parse::<TextEncoding>().is_err());
+    }
+}
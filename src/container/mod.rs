@@ -1,15 +1,21 @@
+mod encoding;
+mod serialize;
+
+pub use encoding::*;
+pub use serialize::*;
+
 use nom::IResult;
 
-use crate::{NomSpan, PResult};
+use crate::{NomSpan, PResult, Sp, WithSpan};
 use std::collections::HashMap;
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 pub(crate) struct KeyVal<'a> {
     pub key: NomSpan<'a>,
     pub val: NomSpan<'a>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Maidata {
     title: String,
     artist: String,
@@ -17,28 +23,36 @@ pub struct Maidata {
     fallback_designer: Option<String>,
     fallback_offset: Option<f32>,
     fallback_single_message: Option<String>,
+    fallback_active_messages: Vec<ActiveMessage>,
 
     // XXX: is wholebpm mandatory?
     star_bpm: Option<f32>,
 
+    demo_seek: Option<f32>,
+    demo_len: Option<f32>,
+    seek: Option<f32>,
+
     difficulties: Vec<BeatmapData>,
-}
 
-impl Default for Maidata {
-    fn default() -> Self {
-        Self {
-            title: String::default(),
-            artist: String::default(),
-            fallback_designer: None,
-            fallback_offset: None,
-            fallback_single_message: None,
-            star_bpm: None,
-            difficulties: vec![],
-        }
-    }
+    /// Keys of all entries, with the spans of their values.
+    value_spans: Vec<(String, crate::Span)>,
+    /// Entries skipped for having invalid values.
+    warnings: Vec<ParseError>,
 }
 
 impl Maidata {
+    /// Decodes and parses the raw content of `maidata.txt`, returning the encoding used too.
+    ///
+    /// See [`decode`] for how `encoding` is detected if not given. Spans in the result are
+    /// positions in the decoded text, without any BOM.
+    pub fn from_bytes(
+        bytes: &[u8],
+        encoding: Option<TextEncoding>,
+    ) -> Result<(Self, TextEncoding), DecodeError> {
+        let (text, encoding) = decode(bytes, encoding)?;
+        Ok((lex_maidata(&text), encoding))
+    }
+
     pub fn title(&self) -> &str {
         &self.title
     }
@@ -47,7 +61,65 @@ impl Maidata {
         &self.artist
     }
 
-    pub fn iter_difficulties(&self) -> impl Iterator<Item = AssociatedBeatmapData> {
+    pub fn star_bpm(&self) -> Option<f32> {
+        self.star_bpm
+    }
+
+    /// Where the preview on song select starts, in seconds since the start of the music.
+    pub fn demo_seek(&self) -> Option<f32> {
+        self.demo_seek
+    }
+
+    /// How long the preview on song select lasts, in seconds.
+    pub fn demo_len(&self) -> Option<f32> {
+        self.demo_len
+    }
+
+    /// Where the editor starts playback, in seconds since the start of the music.
+    pub fn seek(&self) -> Option<f32> {
+        self.seek
+    }
+
+    /// Entries whose values could not be parsed, which are left unset rather than failing the
+    /// whole file.
+    pub fn warnings(&self) -> &[ParseError] {
+        &self.warnings
+    }
+
+    /// Changes the playback rate of the whole song, scaling all tempos, durations and offsets.
+    pub fn scale_tempo(&mut self, scale: crate::transform::TempoScale) {
+        self.fallback_offset = self.fallback_offset.map(|x| scale.apply_duration(x));
+        self.star_bpm = self.star_bpm.map(|x| scale.apply_bpm(x));
+        self.demo_seek = self.demo_seek.map(|x| scale.apply_duration(x));
+        self.demo_len = self.demo_len.map(|x| scale.apply_duration(x));
+        self.seek = self.seek.map(|x| scale.apply_duration(x));
+
+        let scale_messages = |messages: &mut Vec<ActiveMessage>| {
+            for x in messages {
+                x.ts = scale.apply_duration(x.ts);
+            }
+        };
+
+        scale_messages(&mut self.fallback_active_messages);
+        for diff in &mut self.difficulties {
+            diff.offset = diff.offset.map(|x| scale.apply_duration(x));
+            diff.insns = scale.apply_insns(&diff.insns);
+            if let Some(messages) = &mut diff.active_messages {
+                scale_messages(messages);
+            }
+        }
+    }
+
+    /// Returns the key of the entry whose value contains `span`, e.g. `inote_5` for spans of
+    /// instructions of the MASTER chart.
+    pub fn owning_key(&self, span: crate::Span) -> Option<&str> {
+        self.value_spans
+            .iter()
+            .find(|(_, value_span)| value_span.contains(span))
+            .map(|(key, _)| key.as_str())
+    }
+
+    pub fn iter_difficulties(&self) -> impl Iterator<Item = AssociatedBeatmapData<'_>> {
         self.difficulties
             .iter()
             .map(move |diff| AssociatedBeatmapData {
@@ -63,8 +135,19 @@ pub struct BeatmapData {
     designer: Option<String>,
     offset: Option<f32>,
     level: Option<crate::Level>,
+    raw_level: Option<String>,
     insns: Vec<crate::Sp<crate::insn::RawInsn>>,
     single_message: Option<String>,
+    active_messages: Option<Vec<ActiveMessage>>,
+}
+
+/// A message shown at some time during play, from `&amsg_time` and `&amsg_content`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ActiveMessage {
+    /// When to show the message, in seconds since the start of the music. This is the clock
+    /// notes are materialized on, given the chart's offset.
+    pub ts: crate::materialize::TimestampInSeconds,
+    pub content: String,
 }
 
 impl BeatmapData {
@@ -74,8 +157,10 @@ impl BeatmapData {
             designer: None,
             offset: None,
             level: None,
+            raw_level: None,
             insns: vec![],
             single_message: None,
+            active_messages: None,
         }
     }
 }
@@ -102,9 +187,16 @@ impl<'a> AssociatedBeatmapData<'a> {
     }
 
     pub fn level(&self) -> Option<crate::Level> {
-        self.map.level.clone()
+        self.map.level
+    }
+
+    /// The `&lv_N` value as written, kept even if it is not a level understood, e.g. "宴13".
+    pub fn raw_level(&self) -> Option<&str> {
+        self.map.raw_level.as_deref()
     }
 
+    /// Iterates over the instructions of the chart, whose spans are positions in the whole
+    /// `maidata.txt` rather than in the `&inote_N` value.
     pub fn iter_insns(&self) -> impl Iterator<Item = &crate::Sp<crate::insn::RawInsn>> {
         self.map.insns.iter()
     }
@@ -115,86 +207,141 @@ impl<'a> AssociatedBeatmapData<'a> {
             .as_deref()
             .or(self.global.fallback_single_message.as_deref())
     }
+
+    /// Active messages of the chart in chronological order, falling back to the global ones.
+    pub fn active_messages(&self) -> &[ActiveMessage] {
+        self.map
+            .active_messages
+            .as_deref()
+            .unwrap_or(&self.global.fallback_active_messages)
+    }
+
+    /// The active message being shown at `ts`, i.e. the last one shown at or before it.
+    ///
+    /// Timestamps of notes materialized with the chart's offset can be passed as is.
+    pub fn active_message_at(
+        &self,
+        ts: crate::materialize::TimestampInSeconds,
+    ) -> Option<&ActiveMessage> {
+        self.active_messages().iter().rev().find(|x| x.ts <= ts)
+    }
+}
+
+/// A `&key=value` entry of `maidata.txt`, with trailing whitespace stripped from the value.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Entry {
+    pub key: Sp<String>,
+    pub value: Sp<String>,
+}
+
+/// Splits `maidata.txt` into entries without interpreting them, returning `None` if the text is
+/// not made up of entries.
+pub fn lex_entries(x: &str) -> Option<Vec<Entry>> {
+    let kvs = lex_maidata_inner(NomSpan::new(x)).ok()?.1;
+
+    Some(
+        kvs.into_iter()
+            .map(|kv| Entry {
+                key: kv.key.fragment().to_string().with_span(span_of(kv.key)),
+                value: kv.val.fragment().to_string().with_span(span_of(kv.val)),
+            })
+            .collect(),
+    )
+}
+
+fn span_of(x: NomSpan) -> crate::Span {
+    use nom::Slice;
+
+    crate::Span::from_start_end(x, x.slice(x.fragment().len()..))
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParseError {
+    /// The text is not made up of `&key=value` entries.
+    MalformedEntries,
+    /// The value of the given key is invalid; the span is that of the value.
+    InvalidValue(Sp<String>),
 }
 
-pub fn lex_maidata<'a>(x: &'a str) -> Maidata {
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::MalformedEntries => write!(f, "expected `&key=value` entries"),
+            ParseError::InvalidValue(key) => write!(
+                f,
+                "invalid value of `&{}` at {}:{}",
+                **key,
+                key.span().line,
+                key.span().col
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses `maidata.txt`, panicking if it is malformed. See [`parse_maidata`] for the fallible
+/// version.
+pub fn lex_maidata(x: &str) -> Maidata {
+    match parse_maidata(x) {
+        Ok(x) => x,
+        Err(e) => panic!("parse maidata failed: {}", e),
+    }
+}
+
+pub fn parse_maidata(x: &str) -> Result<Maidata, ParseError> {
     let input = NomSpan::new(x);
     let output = lex_maidata_inner(input);
 
-    let kvs = output.ok().expect("parse maidata failed").1;
+    let kvs = output.map_err(|_| ParseError::MalformedEntries)?.1;
 
     let mut result = Maidata::default();
     let mut diff_map: HashMap<crate::Difficulty, BeatmapData> = HashMap::new();
+    // `None` for the global ones
+    let mut amsgs: HashMap<Option<crate::Difficulty>, RawActiveMessages> = HashMap::new();
     for kv in kvs {
         let k = *kv.key.fragment();
         let v = *kv.val.fragment();
-
-        let mut handled = false;
-        // difficulty-specific variables
-        macro_rules! handle_one_diff {
-            ( $num: literal => $diff: expr ) => {
-                match k {
-                    concat!("des_", stringify!($num)) => {
-                        let mut data = diff_map
-                            .entry($diff)
-                            .or_insert(BeatmapData::default_with_difficulty($diff));
-                        data.designer = Some(v.to_owned());
-                        handled = true;
-                    }
-                    concat!("first_", stringify!($num)) => {
-                        let mut data = diff_map
-                            .entry($diff)
-                            .or_insert(BeatmapData::default_with_difficulty($diff));
-                        data.offset = Some(v.parse().expect("parse offset failed"));
-                        handled = true;
-                    }
-                    concat!("inote_", stringify!($num)) => {
-                        let mut data = diff_map
-                            .entry($diff)
-                            .or_insert(BeatmapData::default_with_difficulty($diff));
-                        data.insns = crate::insn::parse_maidata_insns(kv.val)
-                            .ok()
-                            .expect("parse insns failed")
+        result.value_spans.push((k.to_owned(), span_of(kv.val)));
+        let invalid_value = || ParseError::InvalidValue(k.to_owned().with_span(span_of(kv.val)));
+
+        // difficulty-specific variables, suffixed with `_N`
+        if let Some((name, diff)) = split_difficulty_suffix(k) {
+            let mut handled = true;
+            match name {
+                "des" => {
+                    beatmap_data(&mut diff_map, diff).designer = Some(v.to_owned());
+                }
+                "first" => {
+                    beatmap_data(&mut diff_map, diff).offset =
+                        Some(v.parse().map_err(|_| invalid_value())?);
+                }
+                "inote" => {
+                    beatmap_data(&mut diff_map, diff).insns =
+                        crate::insn::parse_maidata_insns(kv.val)
+                            .map_err(|_| invalid_value())?
                             .1;
-                        handled = true;
-                    }
-                    concat!("lv_", stringify!($num)) => {
-                        use std::convert::TryInto;
-
-                        let mut data = diff_map
-                            .entry($diff)
-                            .or_insert(BeatmapData::default_with_difficulty($diff));
-                        match kv.val.try_into() {
-                            Ok(lv) => {
-                                data.level = Some(lv);
-                            }
-                            Err(_) => {
-                                // TODO
-                            }
-                        }
-                        handled = true;
-                    }
-                    concat!("smsg_", stringify!($num)) | concat!("freemsg_", stringify!($num)) => {
-                        let mut data = diff_map
-                            .entry($diff)
-                            .or_insert(BeatmapData::default_with_difficulty($diff));
-                        data.single_message = Some(v.to_owned());
-                        handled = true;
-                    }
-                    _ => {}
                 }
-            };
-        }
-
-        handle_one_diff!(1 => crate::Difficulty::Easy);
-        handle_one_diff!(2 => crate::Difficulty::Basic);
-        handle_one_diff!(3 => crate::Difficulty::Advanced);
-        handle_one_diff!(4 => crate::Difficulty::Expert);
-        handle_one_diff!(5 => crate::Difficulty::Master);
-        handle_one_diff!(6 => crate::Difficulty::ReMaster);
-        handle_one_diff!(7 => crate::Difficulty::Original);
-        if handled {
-            continue;
+                "lv" => {
+                    // keep the text for levels not understood, e.g. utage ones
+                    let data = beatmap_data(&mut diff_map, diff);
+                    data.level = v.parse().ok();
+                    data.raw_level = Some(v.to_owned());
+                }
+                "smsg" | "freemsg" => {
+                    beatmap_data(&mut diff_map, diff).single_message = Some(v.to_owned());
+                }
+                "amsg_time" => {
+                    amsgs.entry(Some(diff)).or_default().times = Some(kv);
+                }
+                "amsg_content" => {
+                    amsgs.entry(Some(diff)).or_default().contents = Some(kv);
+                }
+                _ => handled = false,
+            }
+            if handled {
+                continue;
+            }
         }
 
         // global variables
@@ -205,32 +352,127 @@ pub fn lex_maidata<'a>(x: &'a str) -> Maidata {
             "artist" => {
                 result.artist = v.to_owned();
             }
-            "first" => {
-                match v.parse() {
-                    Ok(offset) => {
-                        result.fallback_offset = Some(offset);
-                    }
-                    Err(_) => {
-                        // TODO
-                    }
-                }
-            }
+            // metadata with invalid values is left unset, instead of failing the whole file
+            "first" => match v.parse() {
+                Ok(offset) => result.fallback_offset = Some(offset),
+                Err(_) => result.warnings.push(invalid_value()),
+            },
+            "wholebpm" => match v.parse() {
+                Ok(bpm) => result.star_bpm = Some(bpm),
+                Err(_) => result.warnings.push(invalid_value()),
+            },
             "des" => {
                 result.fallback_designer = Some(v.to_owned());
             }
             "smsg" | "freemsg" => {
                 result.fallback_single_message = Some(v.to_owned());
             }
+            "amsg_time" => {
+                amsgs.entry(None).or_default().times = Some(kv);
+            }
+            "amsg_content" => {
+                amsgs.entry(None).or_default().contents = Some(kv);
+            }
+            "demo_seek" => match parse_seconds(v) {
+                Some(x) => result.demo_seek = Some(x),
+                None => result.warnings.push(invalid_value()),
+            },
+            "demo_len" => match parse_seconds(v) {
+                Some(x) => result.demo_len = Some(x),
+                None => result.warnings.push(invalid_value()),
+            },
+            "seek" => match parse_seconds(v) {
+                Some(x) => result.seek = Some(x),
+                None => result.warnings.push(invalid_value()),
+            },
             _ => println!("unimplemented property: {} = {}", k, v),
         }
     }
 
+    for (diff, raw) in amsgs {
+        let messages = raw.parse()?;
+        match diff {
+            Some(diff) => beatmap_data(&mut diff_map, diff).active_messages = Some(messages),
+            None => result.fallback_active_messages = messages,
+        }
+    }
+
     // put parsed difficulties into result
-    result
-        .difficulties
-        .extend(diff_map.into_iter().map(|(_, data)| data));
+    result.difficulties.extend(diff_map.into_values());
 
-    result
+    Ok(result)
+}
+
+/// Parses a time position or length, which must be a finite non-negative number of seconds.
+fn parse_seconds(x: &str) -> Option<f32> {
+    x.trim()
+        .parse()
+        .ok()
+        .filter(|x: &f32| x.is_finite() && *x >= 0.0)
+}
+
+/// The `&amsg_time` and `&amsg_content` entries, which only make sense together.
+#[derive(Default)]
+struct RawActiveMessages<'a> {
+    times: Option<KeyVal<'a>>,
+    contents: Option<KeyVal<'a>>,
+}
+
+impl RawActiveMessages<'_> {
+    /// Pairs the comma-separated times and contents up, sorting the messages by time.
+    fn parse(self) -> Result<Vec<ActiveMessage>, ParseError> {
+        let invalid_value =
+            |kv: &KeyVal| ParseError::InvalidValue(kv.key.to_string().with_span(span_of(kv.val)));
+        let split = |x: &str| -> Vec<String> {
+            let mut items: Vec<_> = x.split(',').map(|x| x.trim().to_owned()).collect();
+            // allow a trailing comma
+            if items.last().is_some_and(|x| x.is_empty()) {
+                items.pop();
+            }
+            items
+        };
+
+        let (times, contents) = match (self.times, self.contents) {
+            (Some(times), Some(contents)) => (times, contents),
+            (Some(kv), None) | (None, Some(kv)) => return Err(invalid_value(&kv)),
+            (None, None) => return Ok(vec![]),
+        };
+
+        let ts = split(times.val.fragment())
+            .iter()
+            .map(|x| x.parse().map_err(|_| invalid_value(&times)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let texts = split(contents.val.fragment());
+        if ts.len() != texts.len() {
+            return Err(invalid_value(&contents));
+        }
+
+        let mut result: Vec<_> = ts
+            .into_iter()
+            .zip(texts)
+            .map(|(ts, content)| ActiveMessage { ts, content })
+            .collect();
+        result.sort_by(|a, b| a.ts.total_cmp(&b.ts));
+        Ok(result)
+    }
+}
+
+fn beatmap_data(
+    map: &mut HashMap<crate::Difficulty, BeatmapData>,
+    diff: crate::Difficulty,
+) -> &mut BeatmapData {
+    map.entry(diff)
+        .or_insert_with(|| BeatmapData::default_with_difficulty(diff))
+}
+
+/// Splits keys like `inote_5` into the name and the difficulty.
+fn split_difficulty_suffix(k: &str) -> Option<(&str, crate::Difficulty)> {
+    let (name, index) = k.rsplit_once('_')?;
+    if !index.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+
+    Some((name, crate::Difficulty::from_index(index.parse().ok()?)))
 }
 
 fn lex_maidata_inner(s: NomSpan) -> IResult<NomSpan, Vec<KeyVal>> {
@@ -284,7 +526,7 @@ fn num_rightmost_whitespaces<S: AsRef<str>>(x: S) -> usize {
 
     // only work with bytes for now, simplifies things quite a bit
     let x = x.as_ref().as_bytes();
-    if x.len() == 0 {
+    if x.is_empty() {
         return 0;
     }
 
@@ -315,13 +557,32 @@ fn t_level_num(s: NomSpan) -> PResult<crate::Level> {
     use nom::character::complete::digit1;
     use nom::character::complete::multispace0;
     use nom::combinator::opt;
+    use nom::error::ErrorKind;
+    use nom::sequence::preceded;
 
     let (s, num) = digit1(s)?;
+    let (s, frac) = opt(preceded(char('.'), digit1))(s)?;
     let (s, _) = multispace0(s)?;
+
+    if let Some(frac) = frac {
+        // digits always parse, if only to infinity
+        let lv: f32 = format!("{}.{}", num.fragment(), frac.fragment())
+            .parse()
+            .unwrap();
+        if !lv.is_finite() {
+            return Err(nom::Err::Error((num, ErrorKind::Float)));
+        }
+        return Ok((s, crate::Level::Constant(lv)));
+    }
+
     let (s, plus) = opt(char('+'))(s)?;
     let (s, _) = multispace0(s)?;
 
-    let lv = num.fragment().parse().unwrap();
+    let lv = match num.fragment().parse() {
+        Ok(x) => x,
+        // too large to be any level
+        Err(_) => return Err(nom::Err::Error((num, ErrorKind::Digit))),
+    };
 
     Ok((
         s,
@@ -336,8 +597,10 @@ fn t_level_char(s: NomSpan) -> PResult<crate::Level> {
     use nom::character::complete::anychar;
     use nom::character::complete::char;
     use nom::character::complete::multispace0;
+    use nom::combinator::opt;
 
-    let (s, _) = char('※')(s)?;
+    // the mark is sometimes left out, as in the "宴" of utage charts
+    let (s, _) = opt(char('※'))(s)?;
     let (s, _) = multispace0(s)?;
     let (s, ch) = anychar(s)?;
     let (s, _) = multispace0(s)?;
@@ -350,12 +613,32 @@ impl std::convert::TryFrom<NomSpan<'_>> for crate::Level {
 
     fn try_from(value: NomSpan) -> Result<Self, Self::Error> {
         match t_level(value) {
-            Ok((_, value)) => Ok(value),
+            Ok((rest, value)) if rest.fragment().is_empty() => Ok(value),
+            // don't silently drop anything following the level
+            Ok(_) => Err(nom::Err::Error(nom::error::ErrorKind::Eof)),
             Err(e) => Err(e.map(|(_, x)| x)),
         }
     }
 }
 
+#[derive(Clone, Debug)]
+pub enum LevelParseError {
+    InvalidLevel(String),
+}
+
+impl std::str::FromStr for crate::Level {
+    type Err = LevelParseError;
+
+    /// Accepts levels as written in `&lv_N`, e.g. "13", "13+", "13.7", "※?" and "宴".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use std::convert::TryInto;
+
+        NomSpan::new(s.trim())
+            .try_into()
+            .map_err(|_| LevelParseError::InvalidLevel(s.to_owned()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -369,4 +652,159 @@ mod tests {
         assert_eq!(num_rightmost_whitespaces("foo\r\n\r\nbar"), 0);
         assert_eq!(num_rightmost_whitespaces("\n\n\nfoo\n\nbar\n"), 1);
     }
+
+    #[test]
+    fn test_lex_entries() {
+        let entries =
+            super::lex_entries("\u{feff}&title=foo\r\n&inote_5=\r\n(120)1,\r\nE\r\n").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(*entries[1].key, "inote_5");
+        assert_eq!(*entries[1].value, "\r\n(120)1,\r\nE");
+
+        let span = entries[1].value.span();
+        assert_eq!((span.byte_offset, span.len), (24, 12));
+        assert_eq!(
+            (span.line, span.col, span.end_line, span.end_col),
+            (2, 10, 4, 2)
+        );
+
+        assert!(super::lex_entries("foo&title=bar").is_none());
+    }
+
+    #[test]
+    fn test_insn_spans_are_absolute() {
+        const SRC: &str = "\u{feff}&title=foo\r\n&inote_2=(120){4}1,\r\n2h[4:1],E\r\n\
+            &lv_5=12+\r\n&inote_5=\r\n  (150){8}3b,\r\n\t4-6[8:3],\r\nE\r\n";
+
+        let maidata = super::lex_maidata(SRC);
+        let mut diffs: Vec<_> = maidata.iter_difficulties().collect();
+        diffs.sort_by_key(|x| x.difficulty());
+        assert_eq!(diffs.len(), 2);
+
+        let slices = |i: usize| -> Vec<_> {
+            diffs[i]
+                .iter_insns()
+                .map(|x| {
+                    let span = x.span();
+                    let key = format!("inote_{}", diffs[i].difficulty().index());
+                    assert_eq!(maidata.owning_key(span), Some(key.as_str()));
+                    (span.slice(SRC), span.line, span.col)
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            slices(0),
+            vec![
+                ("(120)", 2, 10),
+                ("{4}", 2, 15),
+                ("1,", 2, 18),
+                ("2h[4:1],", 3, 1),
+                ("E", 3, 9),
+            ]
+        );
+        assert_eq!(
+            slices(1),
+            vec![
+                ("(150)", 6, 3),
+                ("{8}", 6, 8),
+                ("3b,", 6, 11),
+                ("4-6[8:3],", 7, 2),
+                ("E", 8, 1),
+            ]
+        );
+
+        assert_eq!(maidata.owning_key(Default::default()), None);
+    }
+
+    #[test]
+    fn test_level() {
+        use crate::Level;
+        use std::convert::TryFrom;
+
+        let parse = |x| Level::try_from(crate::NomSpan::new(x)).ok();
+        assert_eq!(" 13+ ".parse::<Level>().ok(), Some(Level::Plus(13)));
+        assert!("13.7abc".parse::<Level>().is_err());
+        assert_eq!(parse("13"), Some(Level::Normal(13)));
+        assert_eq!(parse("13 +"), Some(Level::Plus(13)));
+        assert_eq!(parse("13.7"), Some(Level::Constant(13.7)));
+        assert_eq!(parse("※?"), Some(Level::Char('?')));
+        assert_eq!(parse("宴"), Some(Level::Char('宴')));
+        assert_eq!(parse("宴13"), None);
+        assert_eq!(parse("13.7+"), None);
+        assert_eq!(parse("300"), None);
+        assert_eq!(parse(&format!("{}.5", "9".repeat(50))), None);
+    }
+
+    #[test]
+    fn test_difficulty_slots() {
+        use crate::{Difficulty, Level};
+
+        let maidata = super::lex_maidata(
+            "&title=foo\n&lv_0=1\n&inote_0=1,E\n&lv_7=宴\n&des_7=bar\n&inote_7=2,E\n\
+             &lv_12=宴13\n&inote_12=3,E\n&foo_3=baz\n",
+        );
+        let mut diffs: Vec<_> = maidata.iter_difficulties().collect();
+        diffs.sort_by_key(|x| x.difficulty());
+
+        let summary: Vec<_> = diffs
+            .iter()
+            .map(|x| {
+                (
+                    x.difficulty(),
+                    x.level(),
+                    x.raw_level(),
+                    x.iter_insns().count(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    Difficulty::from_index(0),
+                    Some(Level::Normal(1)),
+                    Some("1"),
+                    2
+                ),
+                (Difficulty::Original, Some(Level::Char('宴')), Some("宴"), 2),
+                (Difficulty::from_index(12), None, Some("宴13"), 2),
+            ]
+        );
+        assert_eq!(diffs[1].designer(), Some("bar"));
+    }
+
+    #[test]
+    fn test_active_messages() {
+        use super::{ActiveMessage, ParseError};
+
+        let maidata = super::lex_maidata(
+            "&amsg_time=1.5,0.5,\n&amsg_content=world,hello\n&first=1\n\
+             &inote_4=(120){4}1,E\n&amsg_time_5=2\n&amsg_content_5=master\n&inote_5=(120){4}1,E\n",
+        );
+        let mut diffs: Vec<_> = maidata.iter_difficulties().collect();
+        diffs.sort_by_key(|x| x.difficulty());
+
+        let msg = |ts: f32, content: &str| ActiveMessage {
+            ts,
+            content: content.to_owned(),
+        };
+        assert_eq!(
+            diffs[0].active_messages(),
+            &[msg(0.5, "hello"), msg(1.5, "world")]
+        );
+        assert_eq!(diffs[1].active_messages(), &[msg(2.0, "master")]);
+
+        // the first note lands at the offset
+        let mut mcx =
+            crate::materialize::MaterializationContext::with_offset(diffs[0].offset().unwrap());
+        let ts = mcx.materialize_insns(diffs[0].iter_insns())[0].ts();
+        assert_eq!(diffs[0].active_message_at(ts), Some(&msg(0.5, "hello")));
+        assert_eq!(diffs[0].active_message_at(0.0), None);
+
+        match super::parse_maidata("&amsg_time=1,2\n&amsg_content=foo\n") {
+            Err(ParseError::InvalidValue(key)) => assert_eq!(*key, "amsg_content"),
+            x => panic!("unexpected result {:?}", x),
+        }
+    }
 }
@@ -0,0 +1,209 @@
+//! Serialization of parsed `maidata.txt` back into text.
+
+use std::fmt::Write;
+
+use super::{ActiveMessage, Maidata};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SerializeError {
+    /// The value of the given key would not parse back the same, e.g. because it contains `&`
+    /// or, for active messages, `,`.
+    UnrepresentableValue(String),
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::UnrepresentableValue(key) => {
+                write!(f, "value of `&{}` cannot be represented", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {}
+
+/// Serializes `maidata` into the content of `maidata.txt`, one entry per line.
+///
+/// Only the fields understood are emitted, so unknown entries of the parsed text are lost, and
+/// charts are listed in order of difficulty. Values that cannot be written as simai text, as they
+/// contain `&` or end with whitespace, are an error rather than silently changing meaning.
+pub fn serialize_maidata(maidata: &Maidata) -> Result<String, SerializeError> {
+    let mut result = String::new();
+    let mut entry = |key: &str, value: &dyn std::fmt::Display| {
+        let value = value.to_string();
+        // values end at the next `&`, and trailing whitespace is stripped
+        if value.contains('&') || value.trim_end() != value {
+            return Err(SerializeError::UnrepresentableValue(key.to_owned()));
+        }
+        writeln!(result, "&{}={}", key, value).unwrap();
+        Ok(())
+    };
+
+    entry("title", &maidata.title)?;
+    entry("artist", &maidata.artist)?;
+    if let Some(x) = maidata.star_bpm {
+        entry("wholebpm", &x)?;
+    }
+    if let Some(x) = maidata.fallback_offset {
+        entry("first", &x)?;
+    }
+    if let Some(x) = &maidata.fallback_designer {
+        entry("des", x)?;
+    }
+    if let Some(x) = &maidata.fallback_single_message {
+        entry("smsg", x)?;
+    }
+    write_active_messages(&mut entry, "", &maidata.fallback_active_messages)?;
+    if let Some(x) = maidata.demo_seek {
+        entry("demo_seek", &x)?;
+    }
+    if let Some(x) = maidata.demo_len {
+        entry("demo_len", &x)?;
+    }
+    if let Some(x) = maidata.seek {
+        entry("seek", &x)?;
+    }
+
+    let mut diffs: Vec<_> = maidata.difficulties.iter().collect();
+    diffs.sort_by_key(|x| x.difficulty);
+    for diff in diffs {
+        let suffix = format!("_{}", diff.difficulty.index());
+
+        match (&diff.raw_level, diff.level) {
+            (Some(x), _) => entry(&format!("lv{}", suffix), x)?,
+            (None, Some(x)) => entry(&format!("lv{}", suffix), &x)?,
+            (None, None) => {}
+        }
+        if let Some(x) = &diff.designer {
+            entry(&format!("des{}", suffix), x)?;
+        }
+        if let Some(x) = diff.offset {
+            entry(&format!("first{}", suffix), &x)?;
+        }
+        if let Some(x) = &diff.single_message {
+            entry(&format!("smsg{}", suffix), x)?;
+        }
+        if let Some(x) = &diff.active_messages {
+            write_active_messages(&mut entry, &suffix, x)?;
+        }
+        entry(
+            &format!("inote{}", suffix),
+            &crate::insn::serialize_insns(&diff.insns),
+        )?;
+    }
+
+    Ok(result)
+}
+
+fn write_active_messages<F>(
+    entry: &mut F,
+    suffix: &str,
+    messages: &[ActiveMessage],
+) -> Result<(), SerializeError>
+where
+    F: FnMut(&str, &dyn std::fmt::Display) -> Result<(), SerializeError>,
+{
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    // contents are split at `,` and trimmed, and an empty last one is taken for a trailing comma
+    let content_key = format!("amsg_content{}", suffix);
+    let last = messages.len() - 1;
+    for (i, x) in messages.iter().enumerate() {
+        if x.content.contains(',')
+            || x.content.trim() != x.content
+            || (i == last && x.content.is_empty())
+        {
+            return Err(SerializeError::UnrepresentableValue(content_key));
+        }
+    }
+
+    let join = |f: fn(&ActiveMessage) -> String| -> String {
+        messages.iter().map(f).collect::<Vec<_>>().join(",")
+    };
+    entry(&format!("amsg_time{}", suffix), &join(|x| x.ts.to_string()))?;
+    entry(&content_key, &join(|x| x.content.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        const SRC: &str = "&title=foo
+&artist=bar
+&wholebpm=150
+&first=1.5
+&amsg_time=0.5,2
+&amsg_content=hello,world
+&demo_seek=30.25
+&demo_len=15
+&seek=0
+&lv_5=13.7
+&des_5=baz
+&inote_5=(150){4}1,2h[4:1],
+{8}3-5[8:3],E
+&lv_7=宴
+&smsg_7=qux
+&inote_7=1,E
+";
+
+        let maidata = crate::container::lex_maidata(SRC);
+        assert_eq!(maidata.demo_seek(), Some(30.25));
+        assert_eq!(maidata.demo_len(), Some(15.0));
+        assert_eq!(maidata.seek(), Some(0.0));
+        assert_eq!(serialize_maidata(&maidata), Ok(SRC.to_owned()));
+    }
+
+    #[test]
+    fn test_unrepresentable() {
+        fn unrepresentable<F: FnOnce(&mut Maidata)>(f: F) -> String {
+            let mut maidata = Maidata::default();
+            f(&mut maidata);
+            match serialize_maidata(&maidata) {
+                Err(SerializeError::UnrepresentableValue(key)) => key,
+                x => panic!("unexpected result {:?}", x),
+            }
+        }
+
+        assert_eq!(
+            unrepresentable(|x| x.title = "foo\n&bar".to_owned()),
+            "title"
+        );
+        assert_eq!(unrepresentable(|x| x.artist = "foo ".to_owned()), "artist");
+        for content in &["hello, world", " hello", ""] {
+            let key = unrepresentable(|x| {
+                x.fallback_active_messages = vec![ActiveMessage {
+                    ts: 1.0,
+                    content: content.to_string(),
+                }]
+            });
+            assert_eq!(key, "amsg_content", "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn test_invalid_seconds() {
+        use crate::container::ParseError;
+
+        for (src, key) in &[
+            ("&demo_seek=abc\n&inote_5=1,E", "demo_seek"),
+            ("&demo_len=-1\n&inote_5=1,E", "demo_len"),
+            ("&seek=inf\n&inote_5=1,E", "seek"),
+        ] {
+            let maidata = crate::container::lex_maidata(src);
+            assert_eq!(maidata.iter_difficulties().count(), 1);
+            assert_eq!(
+                (maidata.demo_seek(), maidata.demo_len(), maidata.seek()),
+                (None, None, None)
+            );
+            match maidata.warnings() {
+                [ParseError::InvalidValue(x)] => assert_eq!(**x, *key),
+                x => panic!("unexpected warnings {:?}", x),
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::Diagnostic;
+use crate::json::Json;
+use crate::Span;
+
+/// Renders diagnostics as a JSON array, for consumption by CI and other tools.
+///
+/// Each diagnostic becomes an object with the `file`, `severity`, `code` (possibly `null`),
+/// `message`, `labels` and `notes` fields, and the location of its primary label spread into
+/// `line`, `column`, `end_line` and `end_column` (all 1-based, absent if there is none).
+pub fn render_json(file_name: &str, diags: &[Diagnostic]) -> String {
+    let diags: Vec<_> = diags
+        .iter()
+        .map(|diag| diagnostic_to_json(file_name, diag))
+        .collect();
+    Json::from(diags).to_string()
+}
+
+fn diagnostic_to_json(file_name: &str, diag: &Diagnostic) -> Json {
+    let mut fields = vec![
+        ("file", Json::from(file_name)),
+        ("severity", Json::from(diag.severity.to_string())),
+        ("code", diag.code.as_deref().map_or(Json::Null, Json::from)),
+        ("message", Json::from(diag.message.as_str())),
+    ];
+    if let Some(span) = diag.primary_span() {
+        fields.extend(location_fields(span));
+    }
+
+    let labels = diag
+        .labels
+        .iter()
+        .map(|label| {
+            let mut fields = location_fields(label.span);
+            fields.push(("primary", Json::from(label.is_primary)));
+            fields.push(("message", Json::from(label.message.as_str())));
+            Json::object(fields)
+        })
+        .collect::<Vec<_>>();
+    let notes = diag
+        .notes
+        .iter()
+        .map(|x| Json::from(x.as_str()))
+        .collect::<Vec<_>>();
+    fields.push(("labels", labels.into()));
+    fields.push(("notes", notes.into()));
+
+    Json::object(fields)
+}
+
+fn location_fields(span: Span) -> Vec<(&'static str, Json)> {
+    vec![
+        ("line", Json::from(span.line)),
+        ("column", Json::from(span.col)),
+        ("end_line", Json::from(span.end_line)),
+        ("end_column", Json::from(span.end_col)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::Severity;
+
+    #[test]
+    fn test_render_json() {
+        let span = Span {
+            byte_offset: 10,
+            line: 2,
+            col: 1,
+            end_line: 2,
+            end_col: 3,
+            len: 2,
+        };
+        let diags = [
+            Diagnostic::new(Severity::Warning, "chart does not end with `E`")
+                .with_code("missing-end-mark")
+                .with_primary_label(span, "")
+                .with_note("add \"E\""),
+            Diagnostic::new(Severity::Error, "bad"),
+        ];
+
+        assert_eq!(
+            render_json("a\\maidata.txt", &diags),
+            concat!(
+                r#"[{"file":"a\\maidata.txt","severity":"warning","code":"missing-end-mark","#,
+                r#""message":"chart does not end with `E`","line":2,"column":1,"end_line":2,"#,
+                r#""end_column":3,"labels":[{"line":2,"column":1,"end_line":2,"end_column":3,"#,
+                r#""primary":true,"message":""}],"notes":["add \"E\""]},"#,
+                r#"{"file":"a\\maidata.txt","severity":"error","code":null,"message":"bad","#,
+                r#""labels":[],"notes":[]}]"#,
+            ),
+        );
+    }
+}
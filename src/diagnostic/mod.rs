@@ -0,0 +1,82 @@
+//! Reporting problems found in charts, with the source they concern.
+
+mod json;
+mod render;
+
+pub use json::render_json;
+pub use render::Renderer;
+
+use crate::lint::{LintDiagnostic, Severity};
+use crate::{Sp, Span};
+
+/// A piece of source pointed at by a diagnostic.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// Whether this is where the problem lies, rather than some related place.
+    pub is_primary: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Identifier of the kind of problem, e.g. a lint rule ID.
+    pub code: Option<String>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(severity: Severity, message: S) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn with_code<S: Into<String>>(mut self, code: S) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_primary_label<S: Into<String>>(mut self, span: Span, message: S) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            is_primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary_label<S: Into<String>>(mut self, span: Span, message: S) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            is_primary: false,
+        });
+        self
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// The span of the first primary label, used as the location of the whole diagnostic.
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|x| x.is_primary).map(|x| x.span)
+    }
+}
+
+impl From<&Sp<LintDiagnostic>> for Diagnostic {
+    fn from(x: &Sp<LintDiagnostic>) -> Self {
+        Diagnostic::new(x.severity, x.message.clone())
+            .with_code(x.rule.id())
+            .with_primary_label(x.span(), "")
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt::Write;
+
+use super::{Diagnostic, Label};
+use crate::lint::Severity;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics for humans, quoting the source lines they concern.
+///
+/// ```text
+/// error[tap-during-hold]: key 1 is being held at this time
+///  --> maidata.txt:3:13
+///   |
+/// 3 | (120){4}1h[2:1],1,
+///   |                 ^^
+/// ```
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Renderer {
+    color: bool,
+}
+
+impl Renderer {
+    /// Creates a renderer, emitting ANSI color codes if `color` is set.
+    pub fn new(color: bool) -> Self {
+        Self { color }
+    }
+
+    pub fn render(&self, file_name: &str, src: &str, diag: &Diagnostic) -> String {
+        let mut out = String::new();
+        let lines: Vec<_> = src.lines().collect();
+        let severity_color = match diag.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+
+        // header
+        out += &self.paint(severity_color, &diag.severity.to_string());
+        if let Some(code) = &diag.code {
+            out += &self.paint(severity_color, &format!("[{}]", code));
+        }
+        out += &self.paint(BOLD, &format!(": {}", diag.message));
+        out.push('\n');
+
+        let max_line = diag.labels.iter().map(|x| x.span.end_line).max();
+        let gutter_width = max_line.map_or(0, |x| x.to_string().len());
+        let gutter = |out: &mut String, num: Option<usize>| {
+            let num = num.map_or(String::new(), |x| x.to_string());
+            let s = format!("{:>w$} |", num, w = gutter_width);
+            *out += &self.paint(BLUE, &s);
+        };
+
+        if let Some(span) = diag.primary_span() {
+            let _ = writeln!(
+                out,
+                "{}{} {}:{}:{}",
+                " ".repeat(gutter_width),
+                self.paint(BLUE, "-->"),
+                file_name,
+                span.line,
+                span.col
+            );
+        }
+
+        if !diag.labels.is_empty() {
+            gutter(&mut out, None);
+            out.push('\n');
+        }
+
+        for label in &diag.labels {
+            let color = if label.is_primary {
+                severity_color
+            } else {
+                BLUE
+            };
+            let marker = if label.is_primary { '^' } else { '-' };
+
+            for line_num in label.span.line..=label.span.end_line {
+                let line = match lines.get(line_num.wrapping_sub(1)) {
+                    Some(x) => *x,
+                    None => continue,
+                };
+
+                gutter(&mut out, Some(line_num));
+                if !line.is_empty() {
+                    out.push(' ');
+                    out += line;
+                }
+                out.push('\n');
+
+                let (start, end) = underline_cols(label, line_num, line);
+                gutter(&mut out, None);
+                out.push(' ');
+                // keep tabs so the markers line up with the quoted line
+                out.extend(
+                    line.chars()
+                        .take(start - 1)
+                        .map(|c| if c == '\t' { '\t' } else { ' ' }),
+                );
+                let mut underline: String = std::iter::repeat_n(marker, end - start).collect();
+                if line_num == label.span.end_line && !label.message.is_empty() {
+                    underline.push(' ');
+                    underline += &label.message;
+                }
+                out += &self.paint(color, &underline);
+                out.push('\n');
+            }
+        }
+
+        for note in &diag.notes {
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                " ".repeat(gutter_width),
+                self.paint(BLUE, "="),
+                self.paint(BOLD, &format!("note: {}", note)),
+            );
+        }
+
+        out
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_owned()
+        }
+    }
+}
+
+/// Columns of the line to underline, as a half-open range, always covering at least one char.
+fn underline_cols(label: &Label, line_num: usize, line: &str) -> (usize, usize) {
+    let line_end = line.chars().count() + 1;
+    let start = if line_num == label.span.line {
+        label.span.col
+    } else {
+        1
+    };
+    let end = if line_num == label.span.end_line {
+        label.span.end_col
+    } else {
+        line_end
+    };
+
+    let start = start.min(line_end);
+    (start, end.min(line_end).max(start + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Span;
+
+    const SRC: &str = "&inote_5=\n(120){4}1h[2:1],\n\t1,2,\n3,E\n";
+
+    fn span(line: usize, col: usize, end_line: usize, end_col: usize) -> Span {
+        Span {
+            byte_offset: 0,
+            line,
+            col,
+            end_line,
+            end_col,
+            len: 0,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let diag = Diagnostic::new(Severity::Error, "key 1 is being held at this time")
+            .with_code("tap-during-hold")
+            .with_primary_label(span(3, 2, 3, 4), "tapped here")
+            .with_secondary_label(span(2, 9, 2, 17), "")
+            .with_note("holds cannot be tapped again");
+
+        assert_eq!(
+            Renderer::new(false).render("maidata.txt", SRC, &diag),
+            "error[tap-during-hold]: key 1 is being held at this time
+ --> maidata.txt:3:2
+  |
+3 | \t1,2,
+  | \t^^ tapped here
+2 | (120){4}1h[2:1],
+  |         --------
+  = note: holds cannot be tapped again
+",
+        );
+    }
+
+    #[test]
+    fn test_render_multiline() {
+        let diag =
+            Diagnostic::new(Severity::Warning, "foo").with_primary_label(span(3, 4, 4, 2), "");
+
+        assert_eq!(
+            Renderer::new(false).render("a.txt", SRC, &diag),
+            "warning: foo
+ --> a.txt:3:4
+  |
+3 | \t1,2,
+  | \t  ^^
+4 | 3,E
+  | ^
+",
+        );
+
+        let colored = Renderer::new(true).render("a.txt", SRC, &diag);
+        assert!(colored.starts_with("\x1b[1;33mwarning\x1b[0m"));
+    }
+}
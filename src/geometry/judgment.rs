@@ -0,0 +1,471 @@
+//! Judgment areas and arrow counts of slide tracks.
+//!
+//! The tables below are transcribed by hand for tracks starting from key 1, and cover every
+//! shape and distance; other tracks are looked up by rotating and mirroring. The whole table is
+//! pinned by `test_tables`.
+//!
+//! TODO: the `p`/`q` and `pp`/`qq` sequences, the middle areas of `w` and all of the arrow counts
+//! are yet to be checked against the game, and should be corrected here and in `test_tables`
+//! alike when they are.
+
+use super::{mirror_sensor, rotate_sensor};
+use crate::insn::{Key, SlideShape, TouchSensor};
+use TouchSensor::*;
+
+/// Judgment areas of a single-lane track starting from key 1, along with its number of arrows.
+type Table = (&'static [TouchSensor], usize);
+
+/// Judgment information of one slide track.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SlideJudgment {
+    /// The judgment areas the player has to pass through, in order.
+    ///
+    /// Each area is satisfied by touching any one of its sensors. Only the three-lane `w`
+    /// (spread) slides have areas with more than one sensor.
+    pub areas: Vec<Vec<TouchSensor>>,
+    /// Number of arrows drawn along the track.
+    pub num_arrows: usize,
+}
+
+impl SlideJudgment {
+    /// Looks up the judgment areas and arrow count of a slide track.
+    ///
+    /// The areas are tabulated for tracks starting from key 1 and turning clockwise, and are
+    /// mirrored and rotated into place for the others.
+    ///
+    /// Returns `None` if the track cannot be played, see
+    /// [`validate_slide_track`](crate::insn::validate_slide_track).
+    pub fn new(
+        shape: SlideShape,
+        start: Key,
+        interim: Option<Key>,
+        destination: Key,
+    ) -> Option<Self> {
+        let dist = start.clockwise_distance(destination);
+        // distance as seen in the mirrored play area
+        let mirrored_dist = (8 - dist) % 8;
+
+        let ((areas, num_arrows), mirrored) = match shape {
+            SlideShape::Line => by_side(dist, line)?,
+            SlideShape::Arc if dist == 0 || dist == 4 => return None,
+            SlideShape::Arc => by_side(dist, circumference)?,
+            SlideShape::CircumferenceLeft | SlideShape::CircumferenceRight => {
+                // `>` runs clockwise when starting from the upper half of the ring, and the
+                // other way round from the lower half
+                let is_right = shape == SlideShape::CircumferenceRight;
+                if start.is_upper_half() == is_right {
+                    (circumference(dist)?, false)
+                } else {
+                    (circumference(mirrored_dist)?, true)
+                }
+            }
+            SlideShape::V => by_side(dist, v)?,
+            SlideShape::Q => (q(dist), false),
+            SlideShape::P => (q(mirrored_dist), true),
+            SlideShape::Qq => (qq(dist), false),
+            SlideShape::Pp => (qq(mirrored_dist), true),
+            SlideShape::Z => (z(dist)?, false),
+            SlideShape::S => (z(mirrored_dist)?, true),
+            SlideShape::Spread => return spread(start, dist),
+            SlideShape::Angle => return angle(start, interim?, destination),
+        };
+
+        Some(Self {
+            areas: place(areas, start, mirrored).map(|x| vec![x]).collect(),
+            num_arrows,
+        })
+    }
+}
+
+/// Rotates (and mirrors if asked to) areas tabulated for key 1 into place for `start`.
+fn place(
+    areas: &[TouchSensor],
+    start: Key,
+    mirrored: bool,
+) -> impl Iterator<Item = TouchSensor> + '_ {
+    areas.iter().map(move |&x| {
+        let x = if mirrored { mirror_sensor(x) } else { x };
+        rotate_sensor(x, start.index())
+    })
+}
+
+/// Looks up the clockwise half of the ring directly, and the counterclockwise one by mirroring.
+fn by_side(dist: u8, table: fn(u8) -> Option<Table>) -> Option<(Table, bool)> {
+    if dist <= 4 {
+        Some((table(dist)?, false))
+    } else {
+        Some((table(8 - dist)?, true))
+    }
+}
+
+/// `-` tracks.
+fn line(dist: u8) -> Option<Table> {
+    Some(match dist {
+        2 => (&[A1, A2, A3], 9),
+        3 => (&[A1, B2, B3, A4], 12),
+        4 => (&[A1, B1, C, B5, A5], 13),
+        _ => return None,
+    })
+}
+
+/// `^`, `<` and `>` tracks going clockwise; a distance of 0 is a full circle.
+fn circumference(dist: u8) -> Option<Table> {
+    Some(match dist {
+        0 => (&[A1, A2, A3, A4, A5, A6, A7, A8, A1], 41),
+        1 => (&[A1, A2], 5),
+        2 => (&[A1, A2, A3], 10),
+        3 => (&[A1, A2, A3, A4], 15),
+        4 => (&[A1, A2, A3, A4, A5], 20),
+        5 => (&[A1, A2, A3, A4, A5, A6], 26),
+        6 => (&[A1, A2, A3, A4, A5, A6, A7], 31),
+        7 => (&[A1, A2, A3, A4, A5, A6, A7, A8], 36),
+        _ => return None,
+    })
+}
+
+/// `v` tracks, bouncing off the center.
+fn v(dist: u8) -> Option<Table> {
+    Some(match dist {
+        1 => (&[A1, B1, C, B2, A2], 13),
+        2 => (&[A1, B1, C, B3, A3], 13),
+        3 => (&[A1, B1, C, B4, A4], 13),
+        _ => return None,
+    })
+}
+
+/// `q` tracks, looping clockwise around the center.
+fn q(dist: u8) -> Table {
+    match dist {
+        0 => (&[A1, B2, B3, B4, B5, B6, B7, B8, A1], 24),
+        1 => (&[A1, B2, B3, B4, B5, B6, B7, B8, B1, A2], 26),
+        2 => (&[A1, B2, B3, B4, B5, B6, B7, B8, B1, B2, A3], 28),
+        3 => (&[A1, B2, B3, A4], 12),
+        4 => (&[A1, B2, B3, B4, A5], 14),
+        5 => (&[A1, B2, B3, B4, B5, A6], 17),
+        6 => (&[A1, B2, B3, B4, B5, B6, A7], 19),
+        _ => (&[A1, B2, B3, B4, B5, B6, B7, A8], 21),
+    }
+}
+
+/// `qq` tracks, making a wide clockwise loop through the side of the play area.
+fn qq(dist: u8) -> Table {
+    match dist {
+        0 => (&[A1, A2, A3, A4, B4, B5, C, B1, A1], 25),
+        1 => (&[A1, A2, A3, A4, B4, B5, C, B1, B2, A2], 25),
+        2 => (&[A1, A2, A3], 9),
+        3 => (&[A1, A2, A3, A4], 14),
+        4 => (&[A1, A2, A3, A4, A5], 18),
+        5 => (&[A1, A2, A3, A4, B4, B5, A5, A6], 21),
+        6 => (&[A1, A2, A3, A4, B4, B5, B6, B7, A7], 24),
+        _ => (&[A1, A2, A3, A4, B4, B5, C, B8, A8], 25),
+    }
+}
+
+/// `z` tracks, only going to the opposite key.
+fn z(dist: u8) -> Option<Table> {
+    match dist {
+        4 => Some((&[A1, B2, C, B6, A5], 21)),
+        _ => None,
+    }
+}
+
+/// `w` tracks, only going to the opposite key. Each area lists the sensors of the three lanes,
+/// ordered by their destinations going clockwise.
+fn spread(start: Key, dist: u8) -> Option<SlideJudgment> {
+    const AREAS: [[TouchSensor; 3]; 5] = [
+        [A1, A1, A1],
+        [B2, B1, B8],
+        [B3, C, B7],
+        [A4, B5, A6],
+        [A4, A5, A6],
+    ];
+
+    if dist != 4 {
+        return None;
+    }
+
+    let areas = AREAS
+        .iter()
+        .map(|lanes| {
+            let mut area: Vec<_> = vec![];
+            for x in place(lanes, start, false) {
+                if !area.contains(&x) {
+                    area.push(x);
+                }
+            }
+            area
+        })
+        .collect();
+
+    Some(SlideJudgment {
+        areas,
+        num_arrows: 13,
+    })
+}
+
+/// `V` tracks, made of a straight track to the interim key two keys away and another one from
+/// there to the destination.
+fn angle(start: Key, interim: Key, destination: Key) -> Option<SlideJudgment> {
+    let first_dist = start.clockwise_distance(interim);
+    if first_dist != 2 && first_dist != 6 {
+        return None;
+    }
+    let ((first, first_arrows), first_mirrored) = by_side(first_dist, line)?;
+    let ((second, second_arrows), second_mirrored) =
+        by_side(interim.clockwise_distance(destination), line)?;
+
+    // the interim key's area is shared by both legs
+    let areas = place(&first[..2], start, first_mirrored)
+        .chain(place(second, interim, second_mirrored))
+        .map(|x| vec![x])
+        .collect();
+
+    Some(SlideJudgment {
+        areas,
+        num_arrows: first_arrows + second_arrows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn areas(shape: SlideShape, start: Key, dest: Key) -> Vec<Vec<TouchSensor>> {
+        SlideJudgment::new(shape, start, None, dest).unwrap().areas
+    }
+
+    fn single(areas: &[TouchSensor]) -> Vec<Vec<TouchSensor>> {
+        areas.iter().map(|&x| vec![x]).collect()
+    }
+
+    #[test]
+    fn test_straight_lines() {
+        assert_eq!(
+            areas(SlideShape::Line, Key::K1, Key::K5),
+            single(&[A1, B1, C, B5, A5]),
+        );
+        assert_eq!(
+            areas(SlideShape::Line, Key::K1, Key::K3),
+            single(&[A1, A2, A3]),
+        );
+        assert_eq!(
+            areas(SlideShape::Line, Key::K1, Key::K4),
+            single(&[A1, B2, B3, A4]),
+        );
+        assert_eq!(
+            areas(SlideShape::Line, Key::K3, Key::K8),
+            single(&[A3, B2, B1, A8]),
+        );
+        assert_eq!(
+            SlideJudgment::new(SlideShape::Line, Key::K1, None, Key::K5)
+                .unwrap()
+                .num_arrows,
+            13,
+        );
+        assert_eq!(
+            SlideJudgment::new(SlideShape::Line, Key::K1, None, Key::K2),
+            None
+        );
+    }
+
+    #[test]
+    fn test_circumference() {
+        assert_eq!(
+            areas(SlideShape::CircumferenceRight, Key::K1, Key::K3),
+            single(&[A1, A2, A3]),
+        );
+        // 4>6 starts from the lower half, so it goes counterclockwise the long way round
+        assert_eq!(
+            areas(SlideShape::CircumferenceRight, Key::K4, Key::K6),
+            single(&[A4, A3, A2, A1, A8, A7, A6]),
+        );
+        assert_eq!(
+            areas(SlideShape::Arc, Key::K2, Key::K8),
+            single(&[A2, A1, A8]),
+        );
+        assert_eq!(
+            SlideJudgment::new(SlideShape::CircumferenceLeft, Key::K1, None, Key::K1)
+                .unwrap()
+                .num_arrows,
+            41,
+        );
+    }
+
+    #[test]
+    fn test_through_center() {
+        assert_eq!(
+            areas(SlideShape::V, Key::K1, Key::K3),
+            single(&[A1, B1, C, B3, A3]),
+        );
+        assert_eq!(
+            areas(SlideShape::S, Key::K1, Key::K5),
+            single(&[A1, B8, C, B4, A5]),
+        );
+        assert_eq!(
+            areas(SlideShape::Z, Key::K3, Key::K7),
+            single(&[A3, B4, C, B8, A7]),
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        assert_eq!(
+            areas(SlideShape::Q, Key::K1, Key::K5),
+            single(&[A1, B2, B3, B4, A5]),
+        );
+        assert_eq!(
+            areas(SlideShape::P, Key::K1, Key::K5),
+            single(&[A1, B8, B7, B6, A5]),
+        );
+    }
+
+    #[test]
+    fn test_angle() {
+        let judgment =
+            SlideJudgment::new(SlideShape::Angle, Key::K1, Some(Key::K7), Key::K4).unwrap();
+        assert_eq!(judgment.areas, single(&[A1, A8, A7, B6, B5, A4]));
+        assert_eq!(judgment.num_arrows, 21);
+    }
+
+    #[test]
+    fn test_spread() {
+        assert_eq!(
+            areas(SlideShape::Spread, Key::K1, Key::K5),
+            vec![
+                vec![A1],
+                vec![B2, B1, B8],
+                vec![B3, C, B7],
+                vec![A4, B5, A6],
+                vec![A4, A5, A6],
+            ],
+        );
+    }
+
+    /// Sensors of each area, separated by spaces, with the sensors of an area separated by `/`.
+    fn format_areas(areas: &[Vec<TouchSensor>]) -> String {
+        let areas: Vec<_> = areas
+            .iter()
+            .map(|area| {
+                let sensors: Vec<_> = area.iter().map(|x| format!("{:?}", x)).collect();
+                sensors.join("/")
+            })
+            .collect();
+        areas.join(" ")
+    }
+
+    #[test]
+    fn test_tables() {
+        use crate::materialize::{MaterializationContext, Note};
+
+        #[rustfmt::skip]
+        let cases = [
+            ("1-3", "A1 A2 A3", 9),
+            ("1-4", "A1 B2 B3 A4", 12),
+            ("1-5", "A1 B1 C B5 A5", 13),
+            ("1-6", "A1 B8 B7 A6", 12),
+            ("1-7", "A1 A8 A7", 9),
+            ("1^2", "A1 A2", 5),
+            ("1^3", "A1 A2 A3", 10),
+            ("1^4", "A1 A2 A3 A4", 15),
+            ("1^6", "A1 A8 A7 A6", 15),
+            ("1^7", "A1 A8 A7", 10),
+            ("1^8", "A1 A8", 5),
+            ("1<1", "A1 A8 A7 A6 A5 A4 A3 A2 A1", 41),
+            ("1<2", "A1 A8 A7 A6 A5 A4 A3 A2", 36),
+            ("1<3", "A1 A8 A7 A6 A5 A4 A3", 31),
+            ("1<4", "A1 A8 A7 A6 A5 A4", 26),
+            ("1<5", "A1 A8 A7 A6 A5", 20),
+            ("1<6", "A1 A8 A7 A6", 15),
+            ("1<7", "A1 A8 A7", 10),
+            ("1<8", "A1 A8", 5),
+            ("1>1", "A1 A2 A3 A4 A5 A6 A7 A8 A1", 41),
+            ("1>2", "A1 A2", 5),
+            ("1>3", "A1 A2 A3", 10),
+            ("1>4", "A1 A2 A3 A4", 15),
+            ("1>5", "A1 A2 A3 A4 A5", 20),
+            ("1>6", "A1 A2 A3 A4 A5 A6", 26),
+            ("1>7", "A1 A2 A3 A4 A5 A6 A7", 31),
+            ("1>8", "A1 A2 A3 A4 A5 A6 A7 A8", 36),
+            ("1v2", "A1 B1 C B2 A2", 13),
+            ("1v3", "A1 B1 C B3 A3", 13),
+            ("1v4", "A1 B1 C B4 A4", 13),
+            ("1v6", "A1 B1 C B6 A6", 13),
+            ("1v7", "A1 B1 C B7 A7", 13),
+            ("1v8", "A1 B1 C B8 A8", 13),
+            ("1p1", "A1 B8 B7 B6 B5 B4 B3 B2 A1", 24),
+            ("1p2", "A1 B8 B7 B6 B5 B4 B3 A2", 21),
+            ("1p3", "A1 B8 B7 B6 B5 B4 A3", 19),
+            ("1p4", "A1 B8 B7 B6 B5 A4", 17),
+            ("1p5", "A1 B8 B7 B6 A5", 14),
+            ("1p6", "A1 B8 B7 A6", 12),
+            ("1p7", "A1 B8 B7 B6 B5 B4 B3 B2 B1 B8 A7", 28),
+            ("1p8", "A1 B8 B7 B6 B5 B4 B3 B2 B1 A8", 26),
+            ("1q1", "A1 B2 B3 B4 B5 B6 B7 B8 A1", 24),
+            ("1q2", "A1 B2 B3 B4 B5 B6 B7 B8 B1 A2", 26),
+            ("1q3", "A1 B2 B3 B4 B5 B6 B7 B8 B1 B2 A3", 28),
+            ("1q4", "A1 B2 B3 A4", 12),
+            ("1q5", "A1 B2 B3 B4 A5", 14),
+            ("1q6", "A1 B2 B3 B4 B5 A6", 17),
+            ("1q7", "A1 B2 B3 B4 B5 B6 A7", 19),
+            ("1q8", "A1 B2 B3 B4 B5 B6 B7 A8", 21),
+            ("1s5", "A1 B8 C B4 A5", 21),
+            ("1z5", "A1 B2 C B6 A5", 21),
+            ("1pp1", "A1 A8 A7 A6 B6 B5 C B1 A1", 25),
+            ("1pp2", "A1 A8 A7 A6 B6 B5 C B2 A2", 25),
+            ("1pp3", "A1 A8 A7 A6 B6 B5 B4 B3 A3", 24),
+            ("1pp4", "A1 A8 A7 A6 B6 B5 A5 A4", 21),
+            ("1pp5", "A1 A8 A7 A6 A5", 18),
+            ("1pp6", "A1 A8 A7 A6", 14),
+            ("1pp7", "A1 A8 A7", 9),
+            ("1pp8", "A1 A8 A7 A6 B6 B5 C B1 B8 A8", 25),
+            ("1qq1", "A1 A2 A3 A4 B4 B5 C B1 A1", 25),
+            ("1qq2", "A1 A2 A3 A4 B4 B5 C B1 B2 A2", 25),
+            ("1qq3", "A1 A2 A3", 9),
+            ("1qq4", "A1 A2 A3 A4", 14),
+            ("1qq5", "A1 A2 A3 A4 A5", 18),
+            ("1qq6", "A1 A2 A3 A4 B4 B5 A5 A6", 21),
+            ("1qq7", "A1 A2 A3 A4 B4 B5 B6 B7 A7", 24),
+            ("1qq8", "A1 A2 A3 A4 B4 B5 C B8 A8", 25),
+            ("1w5", "A1 B2/B1/B8 B3/C/B7 A4/B5/A6 A4/A5/A6", 13),
+            ("1V31", "A1 A2 A3 A2 A1", 18),
+            ("1V35", "A1 A2 A3 A4 A5", 18),
+            ("1V36", "A1 A2 A3 B4 B5 A6", 21),
+            ("1V37", "A1 A2 A3 B3 C B7 A7", 22),
+            ("1V38", "A1 A2 A3 B2 B1 A8", 21),
+            ("1V71", "A1 A8 A7 A8 A1", 18),
+            ("1V72", "A1 A8 A7 B8 B1 A2", 21),
+            ("1V73", "A1 A8 A7 B7 C B3 A3", 22),
+            ("1V74", "A1 A8 A7 B6 B5 A4", 21),
+            ("1V75", "A1 A8 A7 A6 A5", 18),
+            ("5-7", "A5 A6 A7", 9),
+            ("4>6", "A4 A3 A2 A1 A8 A7 A6", 31),
+            ("6<8", "A6 A7 A8", 10),
+            ("3^1", "A3 A2 A1", 10),
+            ("6q2", "A6 B7 B8 B1 A2", 14),
+            ("7pp3", "A7 A6 A5 A4 A3", 18),
+            ("2w6", "A2 B3/B2/B1 B4/C/B8 A5/B6/A7 A5/A6/A7", 13),
+            ("8V24", "A8 A1 A2 A3 A4", 18),
+            ("3s7", "A3 B2 C B6 A7", 21),
+            ("5v2", "A5 B5 C B2 A2", 13),
+        ];
+
+        for &(track, areas, num_arrows) in &cases {
+            let insns = crate::insn::parse_insns(&format!("(120){{4}}{}[4:1],E", track));
+            let notes = MaterializationContext::with_offset(0.0).materialize_insns(&insns);
+            let judgment = notes
+                .iter()
+                .find_map(|x| match x {
+                    Note::SlideTrack(x) => x.judgment(),
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("{} has no judgment", track));
+
+            assert_eq!(
+                (format_areas(&judgment.areas).as_str(), judgment.num_arrows),
+                (areas, num_arrows),
+                "{}",
+                track,
+            );
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Geometry of the standard maimai play area.
+//!
+//! All coordinates are normalized so that the ring of buttons has radius 1 and is centered at
+//! the origin. The axes follow screen conventions: x grows to the right and y grows downwards,
+//! so increasing angles (as returned by `atan2`) run clockwise on screen.
+
+mod judgment;
+mod sensor;
+mod slide;
+
+pub use judgment::*;
+pub use sensor::*;
+pub use slide::*;
+
+use crate::insn::Key;
+
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub const ORIGIN: Point = Point { x: 0.0, y: 0.0 };
+
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    /// Point at the given distance from the origin, in the direction of `angle`.
+    pub fn from_polar(radius: f32, angle: f32) -> Self {
+        Self {
+            x: radius * angle.cos(),
+            y: radius * angle.sin(),
+        }
+    }
+
+    pub fn len(self) -> f32 {
+        self.x.hypot(self.y)
+    }
+
+    /// Angle of the vector from the origin to this point.
+    pub fn angle(self) -> f32 {
+        self.y.atan2(self.x)
+    }
+
+    pub fn dot(self, other: Point) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn distance(self, other: Point) -> f32 {
+        (other - self).len()
+    }
+
+    /// Linear interpolation between `self` (t = 0) and `other` (t = 1).
+    pub fn lerp(self, other: Point, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl std::ops::Add for Point {
+    type Output = Point;
+
+    fn add(self, rhs: Point) -> Self::Output {
+        Point::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl std::ops::Sub for Point {
+    type Output = Point;
+
+    fn sub(self, rhs: Point) -> Self::Output {
+        Point::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl std::ops::Mul<f32> for Point {
+    type Output = Point;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Point::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl std::ops::Neg for Point {
+    type Output = Point;
+
+    fn neg(self) -> Self::Output {
+        Point::new(-self.x, -self.y)
+    }
+}
+
+/// Radius of the ring of buttons.
+pub const BUTTON_RING_RADIUS: f32 = 1.0;
+
+/// Angle of the given key's button, as seen from the center of the play area.
+///
+/// Key 1 sits 22.5 degrees clockwise from 12 o'clock, and every following key is 45 degrees
+/// further clockwise.
+pub fn key_angle(key: Key) -> f32 {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, FRAC_PI_8};
+
+    -FRAC_PI_2 + FRAC_PI_8 + FRAC_PI_4 * (key.index() as f32)
+}
+
+/// Position of the given key's button.
+pub fn key_position(key: Key) -> Point {
+    Point::from_polar(BUTTON_RING_RADIUS, key_angle(key))
+}
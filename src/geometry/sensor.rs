@@ -0,0 +1,34 @@
+use crate::insn::TouchSensor;
+
+/// Returns the sensor taking the place of `sensor` once the play area is turned clockwise by
+/// `steps` keys.
+pub fn rotate_sensor(sensor: TouchSensor, steps: u8) -> TouchSensor {
+    TouchSensor::from_group_index(sensor.group(), sensor.index() + steps % 8).unwrap()
+}
+
+/// Returns the mirror image of `sensor` across the axis running through keys 1 and 5.
+pub fn mirror_sensor(sensor: TouchSensor) -> TouchSensor {
+    // A and B sensors face keys, while D and E sensors sit between them, half a key further
+    // counterclockwise
+    let idx = match sensor.group() {
+        'D' | 'E' => 9 - sensor.index(),
+        _ => 8 - sensor.index(),
+    };
+    TouchSensor::from_group_index(sensor.group(), idx).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TouchSensor::*;
+
+    #[test]
+    fn test_rotate_and_mirror() {
+        assert_eq!(rotate_sensor(A8, 2), A2);
+        assert_eq!(rotate_sensor(C, 3), C);
+        assert_eq!(mirror_sensor(B2), B8);
+        assert_eq!(mirror_sensor(A1), A1);
+        assert_eq!(mirror_sensor(D2), D1);
+        assert_eq!(mirror_sensor(E1), E2);
+    }
+}
@@ -0,0 +1,339 @@
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use super::{key_angle, key_position, Point};
+use crate::insn::{Key, SlideShape};
+
+/// Radius of the circle `p` and `q` slides travel around, centered at the origin.
+pub const PQ_LOOP_RADIUS: f32 = 0.45;
+
+/// Radius of the circle `pp` and `qq` slides travel around.
+pub const PPQQ_LOOP_RADIUS: f32 = 0.45;
+
+/// Distance from the origin to the center of the circle `pp` and `qq` slides travel around.
+pub const PPQQ_LOOP_CENTER_DISTANCE: f32 = 0.45;
+
+/// Distance from the origin to the two turning points of `s` and `z` slides.
+pub const SZ_CORNER_RADIUS: f32 = 0.5;
+
+/// One piece of a slide path.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Segment {
+    /// A straight line.
+    Line { from: Point, to: Point },
+    /// A circular arc, starting at `start_angle` and sweeping `sweep` radians (positive values
+    /// run clockwise on screen).
+    Arc {
+        center: Point,
+        radius: f32,
+        start_angle: f32,
+        sweep: f32,
+    },
+}
+
+impl Segment {
+    pub fn length(&self) -> f32 {
+        match *self {
+            Segment::Line { from, to } => from.distance(to),
+            Segment::Arc { radius, sweep, .. } => radius * sweep.abs(),
+        }
+    }
+
+    /// Position on the segment at progress `t`, where 0 is the start and 1 is the end.
+    pub fn point_at(&self, t: f32) -> Point {
+        match *self {
+            Segment::Line { from, to } => from.lerp(to, t),
+            Segment::Arc {
+                center,
+                radius,
+                start_angle,
+                sweep,
+            } => center + Point::from_polar(radius, start_angle + sweep * t),
+        }
+    }
+
+    pub fn start_point(&self) -> Point {
+        self.point_at(0.0)
+    }
+
+    pub fn end_point(&self) -> Point {
+        self.point_at(1.0)
+    }
+}
+
+/// The on-screen path of a slide track, made of line segments and circular arcs.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SlidePath {
+    segments: Vec<Segment>,
+}
+
+impl SlidePath {
+    /// Computes the path of a slide track.
+    ///
+    /// Returns `None` if the combination of shape and keys describes no path, e.g. a `^` slide
+    /// to the opposite key (for which the direction is ambiguous), or a `V` slide without its
+    /// interim key.
+    ///
+    /// For the `w` (spread) shape the central track is returned; see [`SlidePath::spread`]
+    /// for all three.
+    pub fn new(
+        shape: SlideShape,
+        start: Key,
+        interim: Option<Key>,
+        destination: Key,
+    ) -> Option<Self> {
+        let from = key_position(start);
+        let to = key_position(destination);
+
+        let segments = match shape {
+            SlideShape::Line | SlideShape::Spread => {
+                if start == destination {
+                    return None;
+                }
+                vec![Segment::Line { from, to }]
+            }
+            SlideShape::Arc => {
+                let cw_steps = start.clockwise_distance(destination);
+                match cw_steps {
+                    0 | 4 => return None,
+                    1..=3 => vec![circumference(start, cw_steps as i8)],
+                    _ => vec![circumference(start, cw_steps as i8 - 8)],
+                }
+            }
+            SlideShape::CircumferenceLeft | SlideShape::CircumferenceRight => {
+                // `>` runs clockwise when starting from the upper half of the ring, and the
+                // other way round from the lower half
+                let is_right = shape == SlideShape::CircumferenceRight;
+                let clockwise = start.is_upper_half() == is_right;
+
+                let steps = if clockwise {
+                    start.clockwise_distance(destination)
+                } else {
+                    destination.clockwise_distance(start)
+                };
+                let steps = if steps == 0 { 8 } else { steps as i8 };
+
+                vec![circumference(start, if clockwise { steps } else { -steps })]
+            }
+            SlideShape::V => vec![
+                Segment::Line {
+                    from,
+                    to: Point::ORIGIN,
+                },
+                Segment::Line {
+                    from: Point::ORIGIN,
+                    to,
+                },
+            ],
+            SlideShape::P | SlideShape::Q => {
+                let dir = if shape == SlideShape::Q { 1.0 } else { -1.0 };
+                tangent_loop(from, to, Point::ORIGIN, PQ_LOOP_RADIUS, dir)
+            }
+            SlideShape::Pp | SlideShape::Qq => {
+                let dir = if shape == SlideShape::Qq { 1.0 } else { -1.0 };
+                // the loop sits to the side of the center the slide turns towards
+                let center = Point::from_polar(
+                    PPQQ_LOOP_CENTER_DISTANCE,
+                    key_angle(start) + PI - dir * FRAC_PI_2,
+                );
+                tangent_loop(from, to, center, PPQQ_LOOP_RADIUS, dir)
+            }
+            SlideShape::S | SlideShape::Z => {
+                let dir = if shape == SlideShape::Z { 1.0 } else { -1.0 };
+                let a = Point::from_polar(SZ_CORNER_RADIUS, key_angle(start) + dir * FRAC_PI_2);
+                let b = -a;
+                vec![
+                    Segment::Line { from, to: a },
+                    Segment::Line { from: a, to: b },
+                    Segment::Line { from: b, to },
+                ]
+            }
+            SlideShape::Angle => {
+                let interim = key_position(interim?);
+                vec![
+                    Segment::Line { from, to: interim },
+                    Segment::Line { from: interim, to },
+                ]
+            }
+        };
+
+        Some(Self { segments })
+    }
+
+    /// Computes all three tracks of a `w` (spread) slide, ordered by their destinations going
+    /// clockwise.
+    pub fn spread(start: Key, destination: Key) -> Option<[Self; 3]> {
+        let from = key_position(start);
+        let line = |key: Key| Self {
+            segments: vec![Segment::Line {
+                from,
+                to: key_position(key),
+            }],
+        };
+
+        if start == destination {
+            return None;
+        }
+
+        Some([
+            line(destination.offset(-1)),
+            line(destination),
+            line(destination.offset(1)),
+        ])
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Total length of the path.
+    pub fn length(&self) -> f32 {
+        self.segments.iter().map(Segment::length).sum()
+    }
+
+    /// Position on the path at progress `t`, where 0 is the start and 1 is the end.
+    ///
+    /// Progress is proportional to the distance travelled along the path; values outside
+    /// `[0, 1]` are clamped.
+    pub fn point_at(&self, t: f32) -> Point {
+        let t = t.clamp(0.0, 1.0);
+        let mut remaining = self.length() * t;
+
+        for seg in &self.segments {
+            let len = seg.length();
+            if remaining <= len {
+                let local_t = if len > 0.0 { remaining / len } else { 0.0 };
+                return seg.point_at(local_t);
+            }
+            remaining -= len;
+        }
+
+        // only reachable due to accumulated rounding errors
+        self.segments
+            .last()
+            .map_or(Point::ORIGIN, Segment::end_point)
+    }
+
+    pub fn start_point(&self) -> Point {
+        self.point_at(0.0)
+    }
+
+    pub fn end_point(&self) -> Point {
+        self.point_at(1.0)
+    }
+}
+
+/// Arc along the ring of buttons, starting at `start` and going `steps` keys clockwise (or
+/// counterclockwise for negative values).
+fn circumference(start: Key, steps: i8) -> Segment {
+    Segment::Arc {
+        center: Point::ORIGIN,
+        radius: super::BUTTON_RING_RADIUS,
+        start_angle: key_angle(start),
+        sweep: FRAC_PI_4 * steps as f32,
+    }
+}
+
+/// Path going from `from` onto the circle around `center` along a tangent, following the circle
+/// in direction `dir` (1 for clockwise, -1 for counterclockwise), and leaving it along another
+/// tangent towards `to`.
+///
+/// Both `from` and `to` must lie outside the circle.
+fn tangent_loop(from: Point, to: Point, center: Point, radius: f32, dir: f32) -> Vec<Segment> {
+    let entry_offset = (radius / from.distance(center)).acos();
+    let entry_angle = (from - center).angle() + dir * entry_offset;
+    let exit_offset = (radius / to.distance(center)).acos();
+    let exit_angle = (to - center).angle() - dir * exit_offset;
+
+    let mut sweep = (dir * (exit_angle - entry_angle)).rem_euclid(2.0 * PI);
+    if sweep < 1e-4 {
+        sweep += 2.0 * PI;
+    }
+
+    let entry = center + Point::from_polar(radius, entry_angle);
+    let exit = center + Point::from_polar(radius, exit_angle);
+
+    vec![
+        Segment::Line { from, to: entry },
+        Segment::Arc {
+            center,
+            radius,
+            start_angle: entry_angle,
+            sweep: dir * sweep,
+        },
+        Segment::Line { from: exit, to },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_SHAPES: [SlideShape; 13] = [
+        SlideShape::Line,
+        SlideShape::Arc,
+        SlideShape::CircumferenceLeft,
+        SlideShape::CircumferenceRight,
+        SlideShape::V,
+        SlideShape::P,
+        SlideShape::Q,
+        SlideShape::S,
+        SlideShape::Z,
+        SlideShape::Pp,
+        SlideShape::Qq,
+        SlideShape::Angle,
+        SlideShape::Spread,
+    ];
+
+    fn assert_close(a: Point, b: Point) {
+        assert!(a.distance(b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_paths_are_continuous_and_hit_keys() {
+        for &shape in &ALL_SHAPES {
+            for &start in &Key::ALL {
+                for &dest in &Key::ALL {
+                    let path = match SlidePath::new(shape, start, Some(start.offset(2)), dest) {
+                        Some(path) => path,
+                        None => continue,
+                    };
+
+                    assert_close(path.start_point(), key_position(start));
+                    assert_close(path.end_point(), key_position(dest));
+                    for w in path.segments().windows(2) {
+                        assert_close(w[0].end_point(), w[1].start_point());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        assert!(SlidePath::new(SlideShape::Line, Key::K1, None, Key::K1).is_none());
+        assert!(SlidePath::new(SlideShape::Arc, Key::K1, None, Key::K5).is_none());
+        assert!(SlidePath::new(SlideShape::Angle, Key::K1, None, Key::K5).is_none());
+    }
+
+    #[test]
+    fn test_circumference_direction() {
+        let quarter = FRAC_PI_4 * 2.0;
+
+        // 1>3 goes clockwise, through the 2 o'clock side
+        let path = SlidePath::new(SlideShape::CircumferenceRight, Key::K1, None, Key::K3).unwrap();
+        assert!((path.length() - quarter).abs() < 1e-4);
+        assert!(path.point_at(0.5).x > 0.9);
+
+        // 4>6 starts from the lower half, so it goes counterclockwise the long way round
+        let path = SlidePath::new(SlideShape::CircumferenceRight, Key::K4, None, Key::K6).unwrap();
+        assert!((path.length() - 3.0 * quarter).abs() < 1e-4);
+
+        // 1<1 is a full circle
+        let path = SlidePath::new(SlideShape::CircumferenceLeft, Key::K1, None, Key::K1).unwrap();
+        assert!((path.length() - 2.0 * PI).abs() < 1e-4);
+
+        // 1^8 takes the short way
+        let path = SlidePath::new(SlideShape::Arc, Key::K1, None, Key::K8).unwrap();
+        assert!((path.length() - FRAC_PI_4).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,728 @@
+//! Lossless concrete syntax tree of instructions.
+//!
+//! Unlike [`RawInsn`], which only keeps the meaning of the chart, the tree keeps every piece of
+//! the source text, including whitespace and `||` comments, so it can be printed back exactly.
+
+use super::*;
+use crate::{Sp, Span, VecSp, WithSpan};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TokenKind {
+    Whitespace,
+    /// A `||` comment running to the end of line, excluding the line break.
+    Comment,
+    /// A run of digits and decimal points, e.g. `120`, `1.5`, or simplified taps like `16`.
+    Number,
+    /// A run of letters, e.g. `b`, `h`, `pp` or `E`.
+    Word,
+    /// Any other single character.
+    Punct,
+}
+
+impl TokenKind {
+    /// Whether the token carries no meaning.
+    pub fn is_trivia(self) -> bool {
+        match self {
+            TokenKind::Whitespace | TokenKind::Comment => true,
+            TokenKind::Number | TokenKind::Word | TokenKind::Punct => false,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+}
+
+/// The syntax form an instruction is written in.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum CstInsnKind {
+    Bpm,
+    BeatDivisor,
+    Rest,
+    Note,
+    /// Taps written together without separators, e.g. `16,`.
+    SimplifiedBundle,
+    /// Notes separated by slashes, e.g. `1/6,`.
+    Bundle,
+    EndMark,
+    /// Tokens that do not form a valid instruction.
+    Error,
+}
+
+/// An instruction along with the tokens it is written in, including preceding trivia.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CstInsn {
+    kind: CstInsnKind,
+    tokens: VecSp<Token>,
+    insn: Option<SpRawInsn>,
+}
+
+impl CstInsn {
+    fn new(tokens: VecSp<Token>) -> Self {
+        let insn = lower_tokens(&tokens);
+        let kind = match insn.as_deref() {
+            Some(RawInsn::Bpm(_)) => CstInsnKind::Bpm,
+            Some(RawInsn::BeatDivisor(_)) => CstInsnKind::BeatDivisor,
+            Some(RawInsn::Rest) => CstInsnKind::Rest,
+            Some(RawInsn::Note(_)) => CstInsnKind::Note,
+            Some(RawInsn::NoteBundle(_)) => {
+                if tokens
+                    .iter()
+                    .any(|t| t.kind == TokenKind::Punct && t.text == "/")
+                {
+                    CstInsnKind::Bundle
+                } else {
+                    CstInsnKind::SimplifiedBundle
+                }
+            }
+            Some(RawInsn::EndMark) => CstInsnKind::EndMark,
+            None => CstInsnKind::Error,
+        };
+
+        Self { kind, tokens, insn }
+    }
+
+    pub fn kind(&self) -> CstInsnKind {
+        self.kind
+    }
+
+    pub fn tokens(&self) -> &[Sp<Token>] {
+        &self.tokens
+    }
+
+    /// Returns the instruction this node lowers to, or `None` for [`CstInsnKind::Error`].
+    pub fn raw(&self) -> Option<&SpRawInsn> {
+        self.insn.as_ref()
+    }
+
+    /// Span of all tokens, including the leading trivia.
+    pub fn span(&self) -> Span {
+        span_of_tokens(&self.tokens)
+    }
+
+    /// Span of the tokens after the leading trivia.
+    pub fn content_span(&self) -> Span {
+        let num_trivia = self
+            .tokens
+            .iter()
+            .take_while(|x| x.kind.is_trivia())
+            .count();
+        span_of_tokens(&self.tokens[num_trivia..])
+    }
+
+    fn end(&self) -> usize {
+        let span = self.span();
+        span.byte_offset + span.len
+    }
+
+    /// Whether the node ends with a terminator, which is only not the case for the last node.
+    fn is_terminated(&self) -> bool {
+        matches!(self.tokens.last(), Some(x) if is_terminator(x))
+    }
+
+    /// Prints the node without whitespace between its meaningful tokens, or as is if that would
+    /// change its meaning.
+    fn format_into(&self, out: &mut String) {
+        let tokens = &self.tokens;
+        let is_removable = |i: usize| {
+            tokens[i].kind == TokenKind::Whitespace
+                && i > 0
+                && i + 1 < tokens.len()
+                && !tokens[i - 1].kind.is_trivia()
+                && !tokens[i + 1].kind.is_trivia()
+        };
+        let kept: VecSp<Token> = (0..tokens.len())
+            .filter(|&i| !is_removable(i))
+            .map(|i| tokens[i].clone())
+            .collect();
+
+        let is_same = match &self.insn {
+            Some(insn) => lower_tokens(&kept).as_deref() == Some(&**insn),
+            None => false,
+        };
+        for token in if is_same { &kept } else { tokens } {
+            out.push_str(&token.text);
+        }
+    }
+}
+
+impl std::fmt::Display for CstInsn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for token in &self.tokens {
+            write!(f, "{}", token.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Concrete syntax tree of a whole chart.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cst {
+    insns: Vec<CstInsn>,
+    trailing_trivia: VecSp<Token>,
+}
+
+impl Cst {
+    /// Parses the text, which never fails as malformed parts become
+    /// [`CstInsnKind::Error`] nodes.
+    pub fn parse(src: &str) -> Self {
+        let mut insns = vec![];
+        let mut builder = InsnBuilder::default();
+
+        for token in Lexer::new(src) {
+            if let Some(insn) = builder.push(token) {
+                insns.push(insn);
+            }
+        }
+
+        let trailing_trivia = builder.finish(&mut insns);
+        Self {
+            insns,
+            trailing_trivia,
+        }
+    }
+
+    /// Applies an edit to the text and parses it again, reusing the nodes not affected by the
+    /// edit with their spans shifted. Returns the new tree along with the indices of the nodes
+    /// parsed anew.
+    ///
+    /// Panics if the edited range is out of bounds or does not lie on char boundaries.
+    pub fn reparse(&self, edit: &TextEdit) -> (Self, std::ops::Range<usize>) {
+        let old_src = self.to_string();
+        let mut new_src = old_src.clone();
+        new_src.replace_range(edit.range.clone(), &edit.replacement);
+
+        // nodes ending before the edit are kept as is, unless the edit could extend their last
+        // token (only words can be terminators and extended at the same time)
+        let num_kept = self
+            .insns
+            .iter()
+            .take_while(|x| {
+                let end = x.end();
+                x.is_terminated()
+                    && (end < edit.range.start
+                        || end == edit.range.start
+                            && x.tokens.last().unwrap().kind != TokenKind::Word)
+            })
+            .count();
+        let mut insns = self.insns[..num_kept].to_vec();
+
+        let lexer = match insns.last() {
+            Some(x) => {
+                let span = x.span();
+                Lexer::new_at(
+                    &new_src,
+                    span.byte_offset + span.len,
+                    span.end_line,
+                    span.end_col,
+                )
+            }
+            None => Lexer::new(&new_src),
+        };
+
+        // nodes after the edit are reused once a new node ends where an old node did
+        let new_edit_end = edit.range.start + edit.replacement.len();
+        let mut old_idx = num_kept;
+        let mut builder = InsnBuilder::default();
+        let mut synced_at = None;
+        for token in lexer {
+            let insn = match builder.push(token) {
+                Some(x) => x,
+                None => continue,
+            };
+            let new_end = insn.end();
+            insns.push(insn);
+
+            if new_end < new_edit_end {
+                continue;
+            }
+            let old_end = new_end + edit.range.len() - edit.replacement.len();
+            while old_idx < self.insns.len() && self.insns[old_idx].end() < old_end {
+                old_idx += 1;
+            }
+            if old_idx < self.insns.len() && self.insns[old_idx].end() == old_end {
+                synced_at = Some(old_idx + 1);
+                break;
+            }
+        }
+        let reparsed = num_kept..insns.len();
+
+        let trailing_trivia = match synced_at {
+            Some(idx) => {
+                let shift = SpanShift::new(&old_src, &new_src, edit);
+                insns.extend(self.insns[idx..].iter().map(|x| shift.apply_insn(x)));
+                shift.apply_tokens(&self.trailing_trivia)
+            }
+            None => builder.finish(&mut insns),
+        };
+
+        let result = Self {
+            insns,
+            trailing_trivia,
+        };
+        (result, reparsed)
+    }
+
+    pub fn insns(&self) -> &[CstInsn] {
+        &self.insns
+    }
+
+    /// Prints the tree back with whitespace inside instructions removed, keeping comments, line
+    /// breaks and other trivia between instructions intact. Malformed nodes are kept as is.
+    pub fn format(&self) -> String {
+        let mut result = String::new();
+        for insn in &self.insns {
+            insn.format_into(&mut result);
+        }
+        for token in &self.trailing_trivia {
+            result.push_str(&token.text);
+        }
+        result
+    }
+
+    /// Trivia after the last instruction.
+    pub fn trailing_trivia(&self) -> &[Sp<Token>] {
+        &self.trailing_trivia
+    }
+
+    /// Converts the tree into instructions, returning the spans of malformed nodes (without
+    /// their leading trivia) if any.
+    pub fn lower(&self) -> Result<Vec<SpRawInsn>, Vec<Span>> {
+        let errors: Vec<_> = self
+            .insns
+            .iter()
+            .filter(|x| x.kind == CstInsnKind::Error)
+            .map(CstInsn::content_span)
+            .collect();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(self.insns.iter().filter_map(|x| x.insn.clone()).collect())
+    }
+}
+
+impl std::fmt::Display for Cst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for insn in &self.insns {
+            write!(f, "{}", insn)?;
+        }
+        for token in &self.trailing_trivia {
+            write!(f, "{}", token.text)?;
+        }
+        Ok(())
+    }
+}
+
+fn span_of_tokens(tokens: &[Sp<Token>]) -> Span {
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => {
+            let (first, last) = (first.span(), last.span());
+            Span {
+                byte_offset: first.byte_offset,
+                line: first.line,
+                col: first.col,
+                end_line: last.end_line,
+                end_col: last.end_col,
+                len: last.byte_offset + last.len - first.byte_offset,
+            }
+        }
+        _ => Span::default(),
+    }
+}
+
+/// Groups tokens into instruction nodes.
+#[derive(Default)]
+struct InsnBuilder {
+    tokens: VecSp<Token>,
+    has_content: bool,
+}
+
+impl InsnBuilder {
+    /// Adds a token, returning the node it completes if any.
+    fn push(&mut self, token: Sp<Token>) -> Option<CstInsn> {
+        let ends_insn = is_terminator(&token);
+        self.has_content |= !token.kind.is_trivia();
+        self.tokens.push(token);
+
+        if ends_insn {
+            self.has_content = false;
+            Some(CstInsn::new(std::mem::take(&mut self.tokens)))
+        } else {
+            None
+        }
+    }
+
+    /// Adds the last node if it is unterminated, returning the trailing trivia.
+    fn finish(mut self, insns: &mut Vec<CstInsn>) -> VecSp<Token> {
+        if self.has_content {
+            insns.push(CstInsn::new(std::mem::take(&mut self.tokens)));
+        }
+        self.tokens
+    }
+}
+
+fn is_terminator(token: &Token) -> bool {
+    match token.kind {
+        TokenKind::Punct => [",", ")", "}"].contains(&token.text.as_str()),
+        TokenKind::Word => token.text == "E",
+        _ => false,
+    }
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Self {
+        Self::new_at(src, 0, 1, 1)
+    }
+
+    fn new_at(src: &'a str, pos: usize, line: usize, col: usize) -> Self {
+        Self {
+            src,
+            pos,
+            line,
+            col,
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Sp<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.src[self.pos..];
+        let ch = rest.chars().next()?;
+
+        let (kind, len) = if rest.starts_with("||") {
+            (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if ch.is_whitespace() {
+            (TokenKind::Whitespace, prefix_len(rest, char::is_whitespace))
+        } else if ch.is_ascii_digit() {
+            (
+                TokenKind::Number,
+                prefix_len(rest, |c| c.is_ascii_digit() || c == '.'),
+            )
+        } else if ch.is_ascii_alphabetic() {
+            (
+                TokenKind::Word,
+                prefix_len(rest, |c| c.is_ascii_alphabetic()),
+            )
+        } else {
+            (TokenKind::Punct, ch.len_utf8())
+        };
+
+        let text = &rest[..len];
+        let (start_line, start_col) = (self.line, self.col);
+        let (line_delta, end_col) = advance(0, self.col, text);
+        self.line += line_delta;
+        self.col = end_col;
+
+        let span = Span {
+            byte_offset: self.pos,
+            line: start_line,
+            col: start_col,
+            end_line: self.line,
+            end_col: self.col,
+            len,
+        };
+        self.pos += len;
+
+        let token = Token {
+            kind,
+            text: text.to_owned(),
+        };
+        Some(token.with_span(span))
+    }
+}
+
+/// Moves a position past the text, returning the new line and column.
+fn advance(line: usize, col: usize, text: &str) -> (usize, usize) {
+    match text.rfind('\n') {
+        Some(idx) => (
+            line + text.matches('\n').count(),
+            text[idx + 1..].chars().count() + 1,
+        ),
+        None => (line, col + text.chars().count()),
+    }
+}
+
+/// A replacement of a byte range of text.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TextEdit {
+    pub range: std::ops::Range<usize>,
+    pub replacement: String,
+}
+
+/// Moves spans after an edit to their new places.
+struct SpanShift {
+    byte_offset: usize,
+    old_len: usize,
+    new_len: usize,
+    /// Position right after the edit, before and after the edit.
+    old_end: (usize, usize),
+    new_end: (usize, usize),
+}
+
+impl SpanShift {
+    fn new(old_src: &str, new_src: &str, edit: &TextEdit) -> Self {
+        let new_edit_end = edit.range.start + edit.replacement.len();
+        Self {
+            byte_offset: edit.range.start,
+            old_len: edit.range.len(),
+            new_len: edit.replacement.len(),
+            old_end: advance(1, 1, &old_src[..edit.range.end]),
+            new_end: advance(1, 1, &new_src[..new_edit_end]),
+        }
+    }
+
+    /// Shifts a position that is after the edit.
+    fn apply_pos(&self, line: usize, col: usize) -> (usize, usize) {
+        if line == self.old_end.0 {
+            (self.new_end.0, col - self.old_end.1 + self.new_end.1)
+        } else {
+            (line - self.old_end.0 + self.new_end.0, col)
+        }
+    }
+
+    fn apply(&self, span: Span) -> Span {
+        debug_assert!(span.byte_offset >= self.byte_offset + self.old_len);
+
+        let (line, col) = self.apply_pos(span.line, span.col);
+        let (end_line, end_col) = self.apply_pos(span.end_line, span.end_col);
+        Span {
+            byte_offset: span.byte_offset - self.old_len + self.new_len,
+            line,
+            col,
+            end_line,
+            end_col,
+            len: span.len,
+        }
+    }
+
+    fn apply_tokens(&self, tokens: &[Sp<Token>]) -> VecSp<Token> {
+        tokens
+            .iter()
+            .map(|t| (**t).clone().with_span(self.apply(t.span())))
+            .collect()
+    }
+
+    fn apply_insn(&self, insn: &CstInsn) -> CstInsn {
+        CstInsn {
+            kind: insn.kind,
+            tokens: self.apply_tokens(&insn.tokens),
+            insn: insn
+                .insn
+                .as_ref()
+                .map(|x| map_insn_spans(x, &|span| self.apply(span))),
+        }
+    }
+}
+
+fn prefix_len<F: Fn(char) -> bool>(x: &str, pred: F) -> usize {
+    x.find(|c| !pred(c)).unwrap_or(x.len())
+}
+
+fn lower_tokens(tokens: &[Sp<Token>]) -> Option<SpRawInsn> {
+    // the parser knows nothing about comments, blank them out keeping the offsets intact
+    let text: String = tokens
+        .iter()
+        .map(|t| match t.kind {
+            TokenKind::Comment => " ".repeat(t.text.len()),
+            _ => t.text.clone(),
+        })
+        .collect();
+
+    let (_, mut insns) = parse_maidata_insns(crate::NomSpan::new(&text)).ok()?;
+    if insns.len() != 1 {
+        return None;
+    }
+
+    let origin = span_of_tokens(tokens);
+    Some(map_insn_spans(&insns.pop().unwrap(), &|x| x.rebase(origin)))
+}
+
+/// Rebuilds an instruction with all spans inside transformed.
+pub(crate) fn map_insn_spans<F: Fn(Span) -> Span>(insn: &SpRawInsn, f: &F) -> SpRawInsn {
+    let map_note = |note: &SpRawNoteInsn| {
+        let new_note = match &**note {
+            RawNoteInsn::Slide(p) => RawNoteInsn::Slide(SlideParams {
+                start: p.start,
+                tracks: p
+                    .tracks
+                    .iter()
+                    .map(|t| (**t).clone().with_span(f(t.span())))
+                    .collect(),
+            }),
+            x => x.clone(),
+        };
+        new_note.with_span(f(note.span()))
+    };
+
+    let new_insn = match &**insn {
+        RawInsn::Note(note) => RawInsn::Note(map_note(note)),
+        RawInsn::NoteBundle(notes) => RawInsn::NoteBundle(notes.iter().map(map_note).collect()),
+        x => x.clone(),
+    };
+    new_insn.with_span(f(insn.span()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "(120){4}\r\n  1, 16 ,|| comment ✓\n1/6,\n\t2h[4:1] ,\n3-7[8:3],,E\n";
+
+    #[test]
+    fn test_reprint() {
+        let cst = Cst::parse(SRC);
+        assert_eq!(cst.to_string(), SRC);
+        assert_eq!(
+            cst.trailing_trivia(),
+            &[Token {
+                kind: TokenKind::Whitespace,
+                text: "\n".to_owned(),
+            }
+            .with_span(Span::default())][..],
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            Cst::parse(SRC).format(),
+            "(120){4}\r\n  1, 16,|| comment ✓\n1/6,\n\t2h[4:1],\n3-7[8:3],,E\n",
+        );
+        // whitespace next to comments is what ends them
+        assert_eq!(
+            Cst::parse("1h[4:1] || hold\n,2 x,").format(),
+            "1h[4:1] || hold\n,2 x,"
+        );
+    }
+
+    #[test]
+    fn test_kinds() {
+        let kinds: Vec<_> = Cst::parse(SRC).insns().iter().map(|x| x.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CstInsnKind::Bpm,
+                CstInsnKind::BeatDivisor,
+                CstInsnKind::Note,
+                CstInsnKind::SimplifiedBundle,
+                CstInsnKind::Bundle,
+                CstInsnKind::Note,
+                CstInsnKind::Note,
+                CstInsnKind::Rest,
+                CstInsnKind::EndMark,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lower() {
+        let lowered = Cst::parse(SRC).lower().unwrap();
+        let stripped = SRC.replace("|| comment ✓", "");
+        let expected = parse_insns(&stripped);
+        assert_eq!(lowered, expected);
+
+        // spans point into the original text
+        let spans: Vec<_> = lowered.iter().map(|x| x.span()).collect();
+        assert_eq!(&SRC[spans[4].byte_offset..][..spans[4].len], "1/6,");
+        assert_eq!((spans[4].line, spans[4].col), (3, 1));
+        assert_eq!((spans[3].line, spans[3].col), (2, 6));
+        match &*lowered[5] {
+            RawInsn::Note(note) => assert_eq!((note.span().line, note.span().col), (4, 2)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn all_spans(cst: &Cst) -> Vec<Span> {
+        let result = std::cell::RefCell::new(vec![]);
+        for insn in cst.insns() {
+            result
+                .borrow_mut()
+                .extend(insn.tokens().iter().map(Sp::span));
+            if let Some(raw) = insn.raw() {
+                map_insn_spans(raw, &|x| {
+                    result.borrow_mut().push(x);
+                    x
+                });
+            }
+        }
+        result
+            .borrow_mut()
+            .extend(cst.trailing_trivia().iter().map(Sp::span));
+        result.into_inner()
+    }
+
+    #[test]
+    fn test_reparse() {
+        let cst = Cst::parse(SRC);
+        let edits = [
+            (0..0, "  "),
+            (11..12, "2b/3h[4:1]"),
+            (14..23, ""),
+            (23..23, "\n|| a\n"),
+            (29..33, "1h[2:1],\n"),
+            (55..56, "Ex"),
+            (SRC.len()..SRC.len(), "1,"),
+            (9..SRC.len(), "E"),
+        ];
+
+        for (range, replacement) in edits.iter() {
+            let edit = TextEdit {
+                range: range.clone(),
+                replacement: replacement.to_string(),
+            };
+            let mut new_src = SRC.to_owned();
+            new_src.replace_range(range.clone(), replacement);
+
+            let (reparsed, _) = cst.reparse(&edit);
+            let expected = Cst::parse(&new_src);
+            assert_eq!(reparsed, expected);
+            assert_eq!(all_spans(&reparsed), all_spans(&expected));
+        }
+    }
+
+    #[test]
+    fn test_reparse_reuse() {
+        let cst = Cst::parse(SRC);
+        // `2h[4:1]` -> `2h[8:1]`
+        let edit = TextEdit {
+            range: 39..40,
+            replacement: "8".to_owned(),
+        };
+        let (reparsed, range) = cst.reparse(&edit);
+        assert_eq!(range, 5..6);
+        assert_eq!(reparsed.insns().len(), cst.insns().len());
+
+        // removing a terminator merges nodes
+        let idx = SRC.find("1/6,").unwrap() + 3;
+        let edit = TextEdit {
+            range: idx..idx + 1,
+            replacement: "".to_owned(),
+        };
+        let (reparsed, range) = cst.reparse(&edit);
+        assert_eq!(range, 4..5);
+        assert_eq!(reparsed.insns()[4].kind(), CstInsnKind::Error);
+        assert_eq!(reparsed.insns().len(), cst.insns().len() - 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        let cst = Cst::parse("1,2x,3");
+        assert_eq!(cst.to_string(), "1,2x,3");
+        let errors = cst.lower().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].byte_offset, errors[0].len), (2, 3));
+        assert_eq!((errors[1].byte_offset, errors[1].len), (5, 1));
+    }
+}
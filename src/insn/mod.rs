@@ -1,10 +1,16 @@
+mod cst;
 mod directives_ty;
 mod notes_ty;
 mod parser;
+mod serialize;
+mod validate;
 
+pub use cst::*;
 pub use directives_ty::*;
 pub use notes_ty::*;
 pub(crate) use parser::parse_maidata_insns;
+pub use serialize::serialize_insns;
+pub use validate::*;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum RawNoteInsn {
@@ -25,3 +31,9 @@ pub enum RawInsn {
 
 pub type SpRawInsn = crate::Sp<RawInsn>;
 pub type SpRawNoteInsn = crate::Sp<RawNoteInsn>;
+
+/// Parses a chart known to be well-formed.
+#[cfg(test)]
+pub(crate) fn parse_insns(x: &str) -> Vec<SpRawInsn> {
+    parse_maidata_insns(crate::NomSpan::new(x)).unwrap().1
+}
@@ -10,6 +10,47 @@ pub enum Key {
     K8,
 }
 
+impl Key {
+    /// All keys in clockwise order, starting from key 1.
+    pub const ALL: [Key; 8] = [
+        Self::K1,
+        Self::K2,
+        Self::K3,
+        Self::K4,
+        Self::K5,
+        Self::K6,
+        Self::K7,
+        Self::K8,
+    ];
+
+    /// Zero-based index of the key, i.e. `0` for key 1 and `7` for key 8.
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the key with the given zero-based index, wrapping around after key 8.
+    pub fn from_index(idx: u8) -> Self {
+        Self::ALL[(idx % 8) as usize]
+    }
+
+    /// Returns the key `n` positions clockwise from this one (negative `n` for counterclockwise).
+    pub fn offset(self, n: i8) -> Self {
+        Self::from_index((self.index() as i16 + n as i16).rem_euclid(8) as u8)
+    }
+
+    /// Whether the key is on the upper half of the ring, i.e. is one of keys 7, 8, 1 and 2.
+    ///
+    /// This decides the direction of `<` and `>` slides starting from the key.
+    pub fn is_upper_half(self) -> bool {
+        matches!(self, Self::K1 | Self::K2 | Self::K7 | Self::K8)
+    }
+
+    /// Number of steps needed to go clockwise from this key to `to`, in `0..8`.
+    pub fn clockwise_distance(self, to: Key) -> u8 {
+        (to.index() + 8 - self.index()) % 8
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum KeyParseError {
     InvalidKey(char),
@@ -70,18 +111,75 @@ pub enum TouchSensor {
     E8,
 }
 
+impl TouchSensor {
+    #[rustfmt::skip]
+    const ALL: [TouchSensor; 33] = [
+        Self::A1, Self::A2, Self::A3, Self::A4, Self::A5, Self::A6, Self::A7, Self::A8,
+        Self::B1, Self::B2, Self::B3, Self::B4, Self::B5, Self::B6, Self::B7, Self::B8,
+        Self::C,
+        Self::D1, Self::D2, Self::D3, Self::D4, Self::D5, Self::D6, Self::D7, Self::D8,
+        Self::E1, Self::E2, Self::E3, Self::E4, Self::E5, Self::E6, Self::E7, Self::E8,
+    ];
+
+    /// The sensor's group, one of `'A'` to `'E'`.
+    pub fn group(self) -> char {
+        match self as u8 {
+            0..=7 => 'A',
+            8..=15 => 'B',
+            16 => 'C',
+            17..=24 => 'D',
+            _ => 'E',
+        }
+    }
+
+    /// Zero-based index of the sensor within its group, always 0 for C.
+    ///
+    /// A and B sensors are numbered after the key they face, while D and E sensors are numbered
+    /// clockwise starting from 12 o'clock.
+    pub fn index(self) -> u8 {
+        match self as u8 {
+            x @ 0..=16 => x % 8,
+            x => (x - 17) % 8,
+        }
+    }
+
+    /// Returns the sensor with the given group and zero-based index, wrapping around after the
+    /// 8th sensor of a group.
+    pub fn from_group_index(group: char, idx: u8) -> Option<Self> {
+        let base = match group {
+            'A' => 0,
+            'B' => 8,
+            'C' => return Some(Self::C),
+            'D' => 17,
+            'E' => 25,
+            _ => return None,
+        };
+        Some(Self::ALL[base + (idx % 8) as usize])
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Length {
     NumBeats(NumBeatsParams),
     Seconds(f32),
 }
 
+/// How long a slide waits after its star is tapped, when overridden.
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum SlideStopTimeSpec {
+    /// One beat at the given BPM, written `[160#...]`. Beat-relative lengths of the slide are
+    /// counted at this BPM too.
     Bpm(f32),
+    /// The given number of seconds, written `[3##...]`. Beat-relative lengths of the slide are
+    /// counted at the current BPM.
     Seconds(f32),
 }
 
+/// Length of a slide track, with all the forms simai allows:
+///
+/// - `[8:3]`, `[#1.5]`: waits one beat at the current BPM
+/// - `[160#8:3]`, `[160#1.5]`: waits one beat at BPM 160, lasting 8:3 at BPM 160 or 1.5 seconds
+/// - `[3##8:3]`, `[3##1.5]`: waits 3 seconds, lasting 8:3 at the current BPM or 1.5 seconds
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum SlideLength {
     Simple(Length),
@@ -118,13 +216,14 @@ pub enum TapVariant {
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct HoldParams {
     pub key: Key,
-    pub len: Length,
+    /// `None` for holds written without a length, like `1h`.
+    pub len: Option<Length>,
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct SlideParams {
     pub start: TapParams,
-    pub tracks: Vec<SlideTrack>,
+    pub tracks: crate::VecSp<SlideTrack>,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -163,6 +262,24 @@ impl SlideTrack {
         }
     }
 
+    pub fn new(shape: SlideShape, params: SlideTrackParams) -> Self {
+        match shape {
+            SlideShape::Line => Self::Line(params),
+            SlideShape::Arc => Self::Arc(params),
+            SlideShape::CircumferenceLeft => Self::CircumferenceLeft(params),
+            SlideShape::CircumferenceRight => Self::CircumferenceRight(params),
+            SlideShape::V => Self::V(params),
+            SlideShape::P => Self::P(params),
+            SlideShape::Q => Self::Q(params),
+            SlideShape::S => Self::S(params),
+            SlideShape::Z => Self::Z(params),
+            SlideShape::Pp => Self::Pp(params),
+            SlideShape::Qq => Self::Qq(params),
+            SlideShape::Angle => Self::Angle(params),
+            SlideShape::Spread => Self::Spread(params),
+        }
+    }
+
     pub fn params(&self) -> &SlideTrackParams {
         match self {
             SlideTrack::Line(p) => p,
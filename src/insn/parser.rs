@@ -59,8 +59,8 @@ fn t_bpm(s: NomSpan) -> PResult<SpRawInsn> {
     use nom::number::complete::float;
 
     let (s, _) = multispace0(s)?;
-    let (s, _) = char('(')(s)?;
     let (s, start_loc) = nom_locate::position(s)?;
+    let (s, _) = char('(')(s)?;
     let (s, _) = multispace0(s)?;
     let (s, bpm) = float(s)?;
     let (s, _) = multispace0(s)?;
@@ -127,7 +127,7 @@ fn t_beat_divisor(s: NomSpan) -> PResult<SpRawInsn> {
     Ok((s, RawInsn::BeatDivisor(params).with_span(span)))
 }
 
-#[cfg_attr(rustfmt, rustfmt_skip)]
+#[rustfmt::skip]
 fn t_key(s: NomSpan) -> PResult<Key> {
     use std::convert::TryFrom;
     use nom::combinator::map;
@@ -270,12 +270,13 @@ fn t_len(s: NomSpan) -> PResult<Length> {
 
 fn t_hold(s: NomSpan) -> PResult<SpRawNoteInsn> {
     use nom::character::complete::char;
+    use nom::combinator::opt;
 
     let (s, _) = multispace0(s)?;
     let (s, start_loc) = nom_locate::position(s)?;
     let (s, key) = t_key(s)?;
     let (s, _) = char('h')(s)?;
-    let (s, len) = t_len(s)?;
+    let (s, len) = opt(t_len)(s)?;
     let (s, end_loc) = nom_locate::position(s)?;
     let (s, _) = multispace0(s)?;
 
@@ -305,30 +306,46 @@ fn t_slide_len_simple(s: NomSpan) -> PResult<SlideLength> {
     Ok((s, SlideLength::Simple(len)))
 }
 
+fn t_slide_stop_time_spec(s: NomSpan) -> PResult<SlideStopTimeSpec> {
+    use nom::branch::alt;
+    use nom::bytes::complete::tag;
+    use nom::combinator::map;
+    use nom::number::complete::float;
+
+    let (s, x) = float(s)?;
+    let (s, _) = multispace0(s)?;
+    // `##` must come first, for `#` is a prefix of it
+    alt((
+        map(tag("##"), move |_| SlideStopTimeSpec::Seconds(x)),
+        map(tag("#"), move |_| SlideStopTimeSpec::Bpm(x)),
+    ))(s)
+}
+
+// the length following a stop time, where seconds are written without `#`
+fn t_slide_len_spec_after_stop_time(s: NomSpan) -> PResult<Length> {
+    use nom::branch::alt;
+    use nom::combinator::map;
+    use nom::number::complete::float;
+
+    let (s, _) = multispace0(s)?;
+    let (s, len) = alt((t_len_spec_beats, map(float, Length::Seconds)))(s)?;
+    let (s, _) = multispace0(s)?;
+
+    Ok((s, len))
+}
+
 // NOTE: must run after t_slide_len_simple
 fn t_slide_len_custom(s: NomSpan) -> PResult<SlideLength> {
     use nom::character::complete::char;
-    use nom::number::complete::float;
 
     let (s, _) = multispace0(s)?;
     let (s, _) = char('[')(s)?;
     let (s, _) = multispace0(s)?;
-    let (s, x1) = float(s)?;
-    let (s, _) = multispace0(s)?;
-    let (s, _) = char('#')(s)?;
-    let (s, len) = t_len_spec(s)?;
+    let (s, stop_time_spec) = t_slide_stop_time_spec(s)?;
+    let (s, len) = t_slide_len_spec_after_stop_time(s)?;
     let (s, _) = char(']')(s)?;
     let (s, _) = multispace0(s)?;
 
-    // following cases are possible in this combinator:
-    //
-    // - `[160#8:3]` -> stop time=(as in BPM 160) len=8:3
-    // - `[3##1.5]` -> stop time=(absolute 3s) len=1.5s
-    let stop_time_spec = match len {
-        Length::NumBeats(_) => SlideStopTimeSpec::Bpm(x1),
-        Length::Seconds(_) => SlideStopTimeSpec::Seconds(x1),
-    };
-
     Ok((s, SlideLength::Custom(stop_time_spec, len)))
 }
 
@@ -414,7 +431,17 @@ fn t_slide_track_angle(s: NomSpan) -> PResult<SlideTrack> {
     ))
 }
 
-fn t_slide_track(s: NomSpan) -> PResult<SlideTrack> {
+fn t_slide_track(s: NomSpan) -> PResult<crate::Sp<SlideTrack>> {
+    let (s, _) = multispace0(s)?;
+    let (s, start_loc) = nom_locate::position(s)?;
+    let (s, track) = t_slide_track_inner(s)?;
+    let (s, end_loc) = nom_locate::position(s)?;
+
+    let span = (start_loc, end_loc);
+    Ok((s, track.with_span(span)))
+}
+
+fn t_slide_track_inner(s: NomSpan) -> PResult<SlideTrack> {
     nom::branch::alt((
         t_slide_track_line,
         t_slide_track_arc,
@@ -432,7 +459,7 @@ fn t_slide_track(s: NomSpan) -> PResult<SlideTrack> {
     ))(s)
 }
 
-fn t_slide_sep_track(s: NomSpan) -> PResult<SlideTrack> {
+fn t_slide_sep_track(s: NomSpan) -> PResult<crate::Sp<SlideTrack>> {
     use nom::character::complete::char;
 
     let (s, _) = multispace0(s)?;
@@ -526,3 +553,19 @@ fn t_bundle(s: NomSpan) -> PResult<SpRawInsn> {
     let span = (start_loc, end_loc);
     Ok((s, RawInsn::NoteBundle(notes).with_span(span)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bpm_span_covers_parens() {
+        const SRC: &str = "  (120){4}1,E";
+
+        let insns = parse_insns(SRC);
+        assert!(matches!(*insns[0], RawInsn::Bpm(_)));
+        let span = insns[0].span();
+        assert_eq!((span.byte_offset, span.col, span.len), (2, 3, 5));
+        assert_eq!(span.slice(SRC), "(120)");
+    }
+}
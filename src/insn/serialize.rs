@@ -0,0 +1,212 @@
+//! Serialization of instructions back into simai notation.
+
+use std::fmt::{self, Display, Formatter, Write};
+
+use super::*;
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index() + 1)
+    }
+}
+
+impl Display for TouchSensor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TouchSensor::C => write!(f, "C"),
+            _ => write!(f, "{}{}", self.group(), self.index() + 1),
+        }
+    }
+}
+
+impl Display for BpmParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "({})", self.new_bpm)
+    }
+}
+
+impl Display for BeatDivisorParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatDivisorParams::NewDivisor(x) => write!(f, "{{{}}}", x),
+            BeatDivisorParams::NewAbsoluteDuration(x) => write!(f, "{{#{}}}", x),
+        }
+    }
+}
+
+/// The length spec without brackets, i.e. `4:1` or `#1.5`.
+impl Display for Length {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Length::NumBeats(p) => write!(f, "{}:{}", p.divisor, p.num),
+            Length::Seconds(x) => write!(f, "#{}", x),
+        }
+    }
+}
+
+impl Display for SlideLength {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SlideLength::Simple(len) => write!(f, "[{}]", len),
+            SlideLength::Custom(stop_time, len) => {
+                match stop_time {
+                    SlideStopTimeSpec::Bpm(x) => write!(f, "[{}#", x)?,
+                    SlideStopTimeSpec::Seconds(x) => write!(f, "[{}##", x)?,
+                }
+                // seconds are written bare after the stop time
+                match len {
+                    Length::NumBeats(_) => write!(f, "{}]", len),
+                    Length::Seconds(x) => write!(f, "{}]", x),
+                }
+            }
+        }
+    }
+}
+
+impl Display for TapParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.key)?;
+        if self.variant == TapVariant::Break {
+            write!(f, "b")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HoldParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}h", self.key)?;
+        if let Some(len) = self.len {
+            write!(f, "[{}]", len)?;
+        }
+        Ok(())
+    }
+}
+
+impl SlideShape {
+    /// The symbol denoting this shape in simai notation.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            SlideShape::Line => "-",
+            SlideShape::Arc => "^",
+            SlideShape::CircumferenceLeft => "<",
+            SlideShape::CircumferenceRight => ">",
+            SlideShape::V => "v",
+            SlideShape::P => "p",
+            SlideShape::Q => "q",
+            SlideShape::S => "s",
+            SlideShape::Z => "z",
+            SlideShape::Pp => "pp",
+            SlideShape::Qq => "qq",
+            SlideShape::Angle => "V",
+            SlideShape::Spread => "w",
+        }
+    }
+}
+
+impl Display for SlideTrack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let params = self.params();
+
+        write!(f, "{}", self.shape().symbol())?;
+        if let Some(interim) = &params.interim {
+            write!(f, "{}", interim)?;
+        }
+        write!(f, "{}{}", params.destination, params.len)
+    }
+}
+
+impl Display for SlideParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.start)?;
+        for (i, track) in self.tracks.iter().enumerate() {
+            if i > 0 {
+                write!(f, "*")?;
+            }
+            write!(f, "{}", **track)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for RawNoteInsn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RawNoteInsn::Tap(p) => write!(f, "{}", p),
+            RawNoteInsn::Hold(p) => write!(f, "{}", p),
+            RawNoteInsn::Slide(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+impl Display for RawInsn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RawInsn::Bpm(p) => write!(f, "{}", p),
+            RawInsn::BeatDivisor(p) => write!(f, "{}", p),
+            RawInsn::Rest => write!(f, ","),
+            RawInsn::Note(note) => write!(f, "{},", **note),
+            RawInsn::NoteBundle(notes) => {
+                for (i, note) in notes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "/")?;
+                    }
+                    write!(f, "{}", **note)?;
+                }
+                write!(f, ",")
+            }
+            RawInsn::EndMark => write!(f, "E"),
+        }
+    }
+}
+
+/// Serializes instructions into simai notation, suitable for an `&inote_N` value.
+///
+/// A line break is inserted before every BPM or beat divisor change.
+pub fn serialize_insns<'a, I: IntoIterator<Item = &'a SpRawInsn>>(insns: I) -> String {
+    let mut result = String::new();
+    let mut prev_is_directive = false;
+
+    for insn in insns {
+        let is_directive = matches!(&**insn, RawInsn::Bpm(_) | RawInsn::BeatDivisor(_));
+        if is_directive && !prev_is_directive && !result.is_empty() {
+            result.push('\n');
+        }
+        prev_is_directive = is_directive;
+
+        write!(result, "{}", **insn).unwrap();
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(x: &str) -> String {
+        let insns = parse_insns(x);
+        serialize_insns(&insns)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let src = "(120){4}1,2b,,3h[4:1]/4h[#1.5],5-1[8:3]*>3[160#8:3],\n{#0.25}6V82[3##1.5],E";
+        assert_eq!(roundtrip(src), src);
+        assert_eq!(roundtrip("(150) {8} 1 6 , 2/3,"), "(150){8}1/6,2/3,");
+        assert_eq!(
+            roundtrip("(150){8}1h,2h/3h[4:1],4/5h,E"),
+            "(150){8}1h,2h/3h[4:1],4/5h,E"
+        );
+    }
+
+    #[test]
+    fn test_slide_lengths() {
+        let src = "(120){4}1-5[8:3],1-5[#1.5],1-5[160#8:3],1-5[160#2],1-5[3##8:3],1-5[3##1.5],E";
+        assert_eq!(roundtrip(src), src);
+        assert_eq!(
+            roundtrip("1-5[ 3 ## 8 : 3 ],1-5[160 # 2.5],"),
+            "1-5[3##8:3],1-5[160#2.5],"
+        );
+    }
+}
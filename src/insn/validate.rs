@@ -0,0 +1,198 @@
+use super::*;
+use crate::{Sp, WithSpan};
+
+/// Reasons for a slide track to be impossible to play.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SlideValidationError {
+    /// The track ends where it starts, but its shape cannot loop back.
+    SameStartAndDestination(SlideShape),
+    /// The track ends on a key adjacent to its start, which the shape cannot reach.
+    AdjacentDestination(SlideShape),
+    /// The track ends on the key opposite to its start, which the shape cannot reach.
+    OppositeDestination(SlideShape),
+    /// The shape only ever goes to the key opposite to its start, but ends elsewhere.
+    DestinationNotOpposite(SlideShape),
+    /// A `V` track without its turning point.
+    MissingInterim,
+    /// A `V` track whose turning point is not two keys away from its start.
+    InvalidInterim,
+    /// A `V` track whose second leg is not a valid straight line.
+    InvalidSecondLeg,
+}
+
+impl std::fmt::Display for SlideValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use SlideValidationError::*;
+        match self {
+            SameStartAndDestination(shape) => {
+                write!(f, "{:?} slide cannot end on its start key", shape)
+            }
+            AdjacentDestination(shape) => {
+                write!(
+                    f,
+                    "{:?} slide cannot end on a key adjacent to its start",
+                    shape
+                )
+            }
+            OppositeDestination(shape) => {
+                write!(
+                    f,
+                    "{:?} slide cannot end on the key opposite to its start",
+                    shape
+                )
+            }
+            DestinationNotOpposite(shape) => {
+                write!(
+                    f,
+                    "{:?} slide must end on the key opposite to its start",
+                    shape
+                )
+            }
+            MissingInterim => write!(f, "V slide is missing its turning point"),
+            InvalidInterim => write!(f, "V slide must turn two keys away from its start"),
+            InvalidSecondLeg => write!(f, "second leg of V slide is not a valid straight line"),
+        }
+    }
+}
+
+impl std::error::Error for SlideValidationError {}
+
+/// Checks every slide in the instructions, including those in bundles.
+pub fn validate_slides<'a, I: IntoIterator<Item = &'a SpRawInsn>>(
+    insns: I,
+) -> Vec<Sp<SlideValidationError>> {
+    let mut result = vec![];
+
+    for insn in insns {
+        match &**insn {
+            RawInsn::Note(note) => validate_note(note, &mut result),
+            RawInsn::NoteBundle(notes) => {
+                for note in notes {
+                    validate_note(note, &mut result);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+fn validate_note(note: &SpRawNoteInsn, out: &mut Vec<Sp<SlideValidationError>>) {
+    if let RawNoteInsn::Slide(params) = &**note {
+        out.extend(validate_slide(params));
+    }
+}
+
+/// Checks all tracks of a slide, returning the errors along with the span of the offending track.
+pub fn validate_slide(params: &SlideParams) -> Vec<Sp<SlideValidationError>> {
+    params
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            validate_slide_track(params.start.key, track)
+                .err()
+                .map(|e| e.with_span(track.span()))
+        })
+        .collect()
+}
+
+/// Checks whether a slide track starting at `start` can be played.
+pub fn validate_slide_track(start: Key, track: &SlideTrack) -> Result<(), SlideValidationError> {
+    use SlideValidationError::*;
+
+    let shape = track.shape();
+    let params = track.params();
+    let dest = params.destination.key;
+    // clockwise distance from start to destination, in 0..8
+    let dist = start.clockwise_distance(dest);
+
+    match shape {
+        SlideShape::Line => check_line(shape, dist),
+        SlideShape::Arc | SlideShape::V => match dist {
+            0 => Err(SameStartAndDestination(shape)),
+            4 => Err(OppositeDestination(shape)),
+            _ => Ok(()),
+        },
+        // ending on the start key makes a full circle
+        SlideShape::CircumferenceLeft | SlideShape::CircumferenceRight => Ok(()),
+        SlideShape::P | SlideShape::Q | SlideShape::Pp | SlideShape::Qq => Ok(()),
+        SlideShape::S | SlideShape::Z | SlideShape::Spread => match dist {
+            4 => Ok(()),
+            _ => Err(DestinationNotOpposite(shape)),
+        },
+        SlideShape::Angle => {
+            let interim = params.interim.ok_or(MissingInterim)?.key;
+            match start.clockwise_distance(interim) {
+                2 | 6 => {}
+                _ => return Err(InvalidInterim),
+            }
+            if dest == start {
+                return Err(SameStartAndDestination(shape));
+            }
+            check_line(shape, interim.clockwise_distance(dest)).map_err(|_| InvalidSecondLeg)
+        }
+    }
+}
+
+fn check_line(shape: SlideShape, dist: u8) -> Result<(), SlideValidationError> {
+    match dist {
+        0 => Err(SlideValidationError::SameStartAndDestination(shape)),
+        1 | 7 => Err(SlideValidationError::AdjacentDestination(shape)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SlideValidationError::*;
+
+    fn errors_of(x: &str) -> Vec<SlideValidationError> {
+        let insns = parse_insns(x);
+        validate_slides(&insns)
+            .into_iter()
+            .map(Sp::into_inner)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_slides() {
+        assert_eq!(
+            errors_of("1-3[4:1],1-5[4:1],1^2[4:1],1>8[4:1],1q1[4:1],1pp1[4:1],1s5[4:1],1w5[4:1],1>1[4:1],"),
+            vec![],
+        );
+        assert_eq!(errors_of("1V35[4:1],1V75[4:1],"), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_slides() {
+        assert_eq!(
+            errors_of("1-2[4:1],"),
+            vec![AdjacentDestination(SlideShape::Line)]
+        );
+        assert_eq!(
+            errors_of("1^5[4:1],"),
+            vec![OppositeDestination(SlideShape::Arc)]
+        );
+        assert_eq!(
+            errors_of("1^1[4:1],"),
+            vec![SameStartAndDestination(SlideShape::Arc)],
+        );
+        assert_eq!(
+            errors_of("1s4[4:1],"),
+            vec![DestinationNotOpposite(SlideShape::S)],
+        );
+        assert_eq!(errors_of("1V45[4:1],"), vec![InvalidInterim]);
+        assert_eq!(errors_of("1V34[4:1],"), vec![InvalidSecondLeg]);
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let insns = parse_insns("1-5[4:1]*-2[4:1],");
+        let errors = validate_slides(&insns);
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span().col, 10);
+    }
+}
@@ -0,0 +1,317 @@
+//! Just enough JSON for the tools: speaking LSP, storing song indices and reporting diagnostics.
+
+use std::fmt::Write;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<I: IntoIterator<Item = (&'static str, Json)>>(fields: I) -> Self {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Looks up a value along a path of object keys.
+    pub fn path(&self, keys: &[&str]) -> Option<&Json> {
+        keys.iter().try_fold(self, |x, k| x.get(k))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(x) if *x >= 0.0 && x.fract() == 0.0 => Some(*x as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(x: bool) -> Self {
+        Json::Bool(x)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(x: usize) -> Self {
+        Json::Number(x as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(x: &str) -> Self {
+        Json::String(x.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(x: String) -> Self {
+        Json::String(x)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(x: Vec<Json>) -> Self {
+        Json::Array(x)
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(x) => write!(f, "{}", x),
+            Json::Number(x) if x.is_finite() => write!(f, "{}", x),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(x) => write_str(f, x),
+            Json::Array(xs) => {
+                f.write_char('[')?;
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", x)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_str(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut std::fmt::Formatter<'_>, x: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for ch in x.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ParseError {
+    pub byte_offset: usize,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid JSON at byte {}", self.byte_offset)
+    }
+}
+
+pub fn parse(x: &str) -> Result<Json, ParseError> {
+    let mut p = Parser { src: x, pos: 0 };
+    let result = p.value()?;
+    p.skip_ws();
+    if p.pos != x.len() {
+        return Err(p.error());
+    }
+    Ok(result)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self) -> ParseError {
+        ParseError {
+            byte_offset: self.pos,
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, ParseError> {
+        self.skip_ws();
+        match self.rest().chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::String),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, ParseError> {
+        self.expect("{")?;
+        let mut fields = vec![];
+        self.skip_ws();
+        if self.eat("}") {
+            return Ok(Json::Object(fields));
+        }
+
+        loop {
+            self.skip_ws();
+            let key = self.string()?;
+            self.skip_ws();
+            self.expect(":")?;
+            fields.push((key, self.value()?));
+            self.skip_ws();
+            if self.eat("}") {
+                return Ok(Json::Object(fields));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, ParseError> {
+        self.expect("[")?;
+        let mut items = vec![];
+        self.skip_ws();
+        if self.eat("]") {
+            return Ok(Json::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            self.skip_ws();
+            if self.eat("]") {
+                return Ok(Json::Array(items));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, ParseError> {
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(self.rest().len());
+        let x = self.rest()[..len].parse().map_err(|_| self.error())?;
+        self.pos += len;
+        Ok(Json::Number(x))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect("\"")?;
+        let mut result = String::new();
+
+        loop {
+            let ch = self.rest().chars().next().ok_or_else(|| self.error())?;
+            self.pos += ch.len_utf8();
+            match ch {
+                '"' => return Ok(result),
+                '\\' => {
+                    let esc = self.rest().chars().next().ok_or_else(|| self.error())?;
+                    self.pos += 1;
+                    match esc {
+                        '"' | '\\' | '/' => result.push(esc),
+                        'b' => result.push('\u{8}'),
+                        'f' => result.push('\u{c}'),
+                        'n' => result.push('\n'),
+                        'r' => result.push('\r'),
+                        't' => result.push('\t'),
+                        'u' => {
+                            let hi = self.hex4()?;
+                            let code = if (0xd800..0xdc00).contains(&hi) {
+                                self.expect("\\u")?;
+                                let lo = self.hex4()?;
+                                0x10000 + ((hi - 0xd800) << 10) + (lo.wrapping_sub(0xdc00) & 0x3ff)
+                            } else {
+                                hi
+                            };
+                            result.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return Err(self.error()),
+                    }
+                }
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self.rest().get(..4).ok_or_else(|| self.error())?;
+        let x = u32::from_str_radix(digits, 16).map_err(|_| self.error())?;
+        self.pos += 4;
+        Ok(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let src = r#"{"a":[1,-2.5,true,null],"b":"x\"\né🎵","c":{}}"#;
+        let x = parse(src).unwrap();
+        assert_eq!(x.path(&["b"]).unwrap().as_str(), Some("x\"\né🎵"));
+        assert_eq!(
+            x.to_string(),
+            r#"{"a":[1,-2.5,true,null],"b":"x\"\né🎵","c":{}}"#
+        );
+        assert!(parse("{\"a\":}").is_err());
+    }
+}
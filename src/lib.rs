@@ -1,29 +1,167 @@
 pub mod container;
+pub mod diagnostic;
+pub mod geometry;
 pub mod insn;
+pub mod json;
+pub mod lint;
 pub mod materialize;
+pub mod package;
 mod span;
+pub mod transform;
 
 pub use span::*;
 
+/// Difficulty of a chart, i.e. the `N` of `&inote_N` and friends.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum Difficulty {
     /// The EASY difficulty.
-    Easy = 1,
+    Easy,
     /// The BASIC difficulty.
-    Basic = 2,
+    Basic,
     /// The ADVANCED difficulty.
-    Advanced = 3,
+    Advanced,
     /// The EXPERT difficulty.
-    Expert = 4,
+    Expert,
     /// The MASTER difficulty.
-    Master = 5,
+    Master,
     /// The Re:MASTER difficulty.
-    ReMaster = 6,
+    ReMaster,
     /// The ORIGINAL difficulty, previously called mai:EDIT in 2simai.
-    Original = 7,
+    Original,
+    /// Any other slot, e.g. `&inote_0` or the ones beyond 7 used by community packs for utage
+    /// and extra charts.
+    Other(OtherSlot),
 }
 
+/// Index of a difficulty slot without a name, which can only be obtained through
+/// [`Difficulty::from_index`] so it never holds the index of a named difficulty.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct OtherSlot(u8);
+
+impl OtherSlot {
+    pub fn index(self) -> u8 {
+        self.0
+    }
+}
+
+impl Difficulty {
+    /// The named difficulties, in order.
+    pub const ALL: &'static [Difficulty] = &[
+        Difficulty::Easy,
+        Difficulty::Basic,
+        Difficulty::Advanced,
+        Difficulty::Expert,
+        Difficulty::Master,
+        Difficulty::ReMaster,
+        Difficulty::Original,
+    ];
+
+    pub fn from_index(index: u8) -> Self {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|x| x.index() == index)
+            .unwrap_or(Difficulty::Other(OtherSlot(index)))
+    }
+
+    /// The `N` of `&inote_N`.
+    pub fn index(self) -> u8 {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Basic => 2,
+            Difficulty::Advanced => 3,
+            Difficulty::Expert => 4,
+            Difficulty::Master => 5,
+            Difficulty::ReMaster => 6,
+            Difficulty::Original => 7,
+            Difficulty::Other(x) => x.index(),
+        }
+    }
+
+    /// The name as shown in game, e.g. "Re:MASTER", or `None` for other slots.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            Difficulty::Easy => Some("EASY"),
+            Difficulty::Basic => Some("BASIC"),
+            Difficulty::Advanced => Some("ADVANCED"),
+            Difficulty::Expert => Some("EXPERT"),
+            Difficulty::Master => Some("MASTER"),
+            Difficulty::ReMaster => Some("Re:MASTER"),
+            Difficulty::Original => Some("ORIGINAL"),
+            Difficulty::Other(_) => None,
+        }
+    }
+}
+
+/// Difficulties are ordered by their indices.
+impl Ord for Difficulty {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.index().cmp(&other.index())
+    }
+}
+
+impl PartialOrd for Difficulty {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "#{}", self.index()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum DifficultyParseError {
+    UnknownDifficulty(String),
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = DifficultyParseError;
+
+    /// Accepts names case-insensitively, ignoring punctuation and spaces so both "Re:MASTER" and
+    /// "remaster" work, as well as the `N` of `&inote_N`. "mai:EDIT" is taken for ORIGINAL.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalize = |x: &str| -> String {
+            x.chars()
+                .filter(|ch| ch.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect()
+        };
+        let wanted = normalize(s);
+
+        if let Ok(index) = wanted.parse() {
+            return Ok(Self::from_index(index));
+        }
+        if wanted == "maiedit" {
+            return Ok(Difficulty::Original);
+        }
+
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|x| x.name().map(normalize).as_ref() == Some(&wanted))
+            .ok_or_else(|| DifficultyParseError::UnknownDifficulty(s.to_owned()))
+    }
+}
+
+/// Where levels of the special "Lv.<any char>" form go when sorting.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum CharLevelOrder {
+    /// Before all other levels.
+    First,
+    /// After all other levels.
+    #[default]
+    Last,
+    /// As if they were the given chart constant, after levels equal to it.
+    AsConstant(f32),
+}
+
+#[derive(Copy, Clone, Debug)]
 pub enum Level {
     /// The "Lv.X" form.
     Normal(u8),
@@ -31,6 +169,89 @@ pub enum Level {
     Plus(u8),
     /// The special "Lv.<any char>" form.
     Char(char),
+    /// The internal chart constant, e.g. "13.7", always finite.
+    Constant(f32),
+}
+
+impl Level {
+    /// Returns the level as displayed in game, i.e. constants turned into the "X" or "X+" they
+    /// fall into. Constants of X.7 and above are "X+".
+    pub fn bucket(self) -> Self {
+        match self {
+            Level::Constant(x) => {
+                let lv = x.trunc().clamp(0.0, u8::MAX as f32) as u8;
+                // compare in tenths, lest 13.7 be taken for 13.6999...
+                if ((x - x.trunc()) * 10.0).round() >= 7.0 {
+                    Level::Plus(lv)
+                } else {
+                    Level::Normal(lv)
+                }
+            }
+            x => x,
+        }
+    }
+
+    /// Compares levels, placing the special "Lv.<any char>" form according to `char_order`.
+    ///
+    /// Levels are compared by the chart constants they stand for, "X" being X.0 and "X+" X.7.
+    /// Levels standing for the same constant are ordered "X", "X+", constant and then special,
+    /// and special levels among themselves by their characters.
+    pub fn cmp_with(&self, other: &Self, char_order: CharLevelOrder) -> std::cmp::Ordering {
+        // adding zero turns -0.0 into 0.0, which compare equal
+        let key = |x: &Level| -> (u8, f32, u8, char) {
+            match *x {
+                Level::Normal(lv) => (1, lv as f32, 0, '\0'),
+                // so that "13+" stands for exactly what "13.7" parses into
+                Level::Plus(lv) => (1, (lv as f32 * 10.0 + 7.0) / 10.0, 1, '\0'),
+                Level::Constant(lv) => (1, lv + 0.0, 2, '\0'),
+                Level::Char(ch) => match char_order {
+                    CharLevelOrder::First => (0, 0.0, 3, ch),
+                    CharLevelOrder::Last => (2, 0.0, 3, ch),
+                    CharLevelOrder::AsConstant(lv) => (1, lv + 0.0, 3, ch),
+                },
+            }
+        };
+
+        let (a, b) = (key(self), key(other));
+        a.0.cmp(&b.0)
+            .then(a.1.total_cmp(&b.1))
+            .then(a.2.cmp(&b.2))
+            .then(a.3.cmp(&b.3))
+    }
+}
+
+// equal as ordered, so that -0.0 equals 0.0 and NaN constants equal themselves
+impl PartialEq for Level {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Level {}
+
+/// Special levels sort last, see [`Level::cmp_with`] for other orders.
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.cmp_with(other, CharLevelOrder::default())
+    }
+}
+
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::hash::Hash for Level {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Level::Normal(lv) | Level::Plus(lv) => lv.hash(state),
+            Level::Char(lv) => lv.hash(state),
+            // adding zero turns -0.0 into 0.0, which compare equal
+            Level::Constant(lv) => (lv + 0.0).to_bits().hash(state),
+        }
+    }
 }
 
 impl std::fmt::Display for Level {
@@ -40,8 +261,119 @@ impl std::fmt::Display for Level {
             Normal(lv) => write!(f, "{}", lv)?,
             Plus(lv) => write!(f, "{}+", lv)?,
             Char(lv) => write!(f, "{}", lv)?,
+            // keep the decimal point, lest it be taken for a normal level
+            Constant(lv) if lv.fract() == 0.0 => write!(f, "{:.1}", lv)?,
+            Constant(lv) => write!(f, "{}", lv)?,
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_constant() {
+        assert_eq!(Level::Constant(13.7).to_string(), "13.7");
+        assert_eq!(Level::Constant(14.0).to_string(), "14.0");
+
+        assert_eq!(Level::Constant(13.6).bucket(), Level::Normal(13));
+        assert_eq!(Level::Constant(13.7).bucket(), Level::Plus(13));
+        assert_eq!(Level::Constant(14.0).bucket(), Level::Normal(14));
+        assert_eq!(Level::Char('?').bucket(), Level::Char('?'));
+    }
+
+    #[test]
+    fn test_level_eq_agrees_with_ord_and_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |x: Level| {
+            let mut hasher = DefaultHasher::new();
+            x.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let (neg, pos) = (Level::Constant(-0.0), Level::Constant(0.0));
+        assert_eq!(neg, pos);
+        assert_eq!(neg.cmp(&pos), std::cmp::Ordering::Equal);
+        assert_eq!(hash(neg), hash(pos));
+
+        let nan = Level::Constant(f32::NAN);
+        assert_eq!(nan, nan);
+        assert_eq!(nan.cmp(&nan), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn test_level_order() {
+        use Level::*;
+
+        let mut levels = vec![
+            Char('宴'),
+            Constant(13.7),
+            Normal(14),
+            Char('?'),
+            Plus(13),
+            Constant(13.6),
+            Normal(13),
+            Constant(13.0),
+        ];
+        levels.sort();
+        assert_eq!(
+            levels,
+            vec![
+                Normal(13),
+                Constant(13.0),
+                Constant(13.6),
+                Plus(13),
+                Constant(13.7),
+                Normal(14),
+                Char('?'),
+                Char('宴'),
+            ]
+        );
+
+        assert!(Char('?')
+            .cmp_with(&Normal(1), CharLevelOrder::First)
+            .is_lt());
+        assert!(Char('?')
+            .cmp_with(&Plus(14), CharLevelOrder::AsConstant(14.5))
+            .is_lt());
+        assert!(Char('?')
+            .cmp_with(&Constant(14.5), CharLevelOrder::AsConstant(14.5))
+            .is_gt());
+    }
+
+    #[test]
+    fn test_difficulty() {
+        for &diff in Difficulty::ALL {
+            assert_eq!(diff.to_string().parse::<Difficulty>().ok(), Some(diff));
+        }
+        assert_eq!(Difficulty::ReMaster.to_string(), "Re:MASTER");
+        assert_eq!(
+            "re master".parse::<Difficulty>().ok(),
+            Some(Difficulty::ReMaster)
+        );
+        assert_eq!("5".parse::<Difficulty>().ok(), Some(Difficulty::Master));
+        assert_eq!(
+            "mai:EDIT".parse::<Difficulty>().ok(),
+            Some(Difficulty::Original)
+        );
+        assert_eq!(
+            "8".parse::<Difficulty>().ok(),
+            Some(Difficulty::from_index(8))
+        );
+        assert_eq!(Difficulty::from_index(8).to_string(), "#8");
+        assert_eq!(
+            "#8".parse::<Difficulty>().ok(),
+            Some(Difficulty::from_index(8))
+        );
+        assert!("foo".parse::<Difficulty>().is_err());
+        assert!(Difficulty::from_index(0) < Difficulty::Easy);
+        assert!(Difficulty::from_index(8) > Difficulty::Original);
+        assert_eq!(Difficulty::from_index(5), Difficulty::Master);
+        assert!(matches!(Difficulty::from_index(8), Difficulty::Other(x) if x.index() == 8));
+    }
+}
@@ -0,0 +1,180 @@
+//! Checks for common mistakes in charts.
+
+mod rules;
+
+use std::collections::HashMap;
+
+use crate::insn::SpRawInsn;
+use crate::Sp;
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum LintRule {
+    /// Two holds on the same key overlap in time.
+    OverlappingHolds,
+    /// A tap or slide star on a key that is being held.
+    TapDuringHold,
+    /// More than one note on the same key in a bundle.
+    DuplicateKeyInBundle,
+    /// The chart does not end with `E`.
+    MissingEndMark,
+    /// A `{divisor}` change that is overridden before any time passes.
+    UnusedDivisor,
+    /// Notes placed after the `E` end mark.
+    NoteAfterEnd,
+    /// A hold lasting zero time.
+    ZeroLengthHold,
+    /// A `(bpm)` change that is overridden before any time passes.
+    RedundantBpm,
+}
+
+impl LintRule {
+    pub const ALL: [LintRule; 8] = [
+        LintRule::OverlappingHolds,
+        LintRule::TapDuringHold,
+        LintRule::DuplicateKeyInBundle,
+        LintRule::MissingEndMark,
+        LintRule::UnusedDivisor,
+        LintRule::NoteAfterEnd,
+        LintRule::ZeroLengthHold,
+        LintRule::RedundantBpm,
+    ];
+
+    /// Stable identifier of the rule, for use in configuration and reports.
+    pub fn id(self) -> &'static str {
+        match self {
+            LintRule::OverlappingHolds => "overlapping-holds",
+            LintRule::TapDuringHold => "tap-during-hold",
+            LintRule::DuplicateKeyInBundle => "duplicate-key-in-bundle",
+            LintRule::MissingEndMark => "missing-end-mark",
+            LintRule::UnusedDivisor => "unused-divisor",
+            LintRule::NoteAfterEnd => "note-after-end",
+            LintRule::ZeroLengthHold => "zero-length-hold",
+            LintRule::RedundantBpm => "redundant-bpm",
+        }
+    }
+
+    pub fn default_severity(self) -> Severity {
+        match self {
+            LintRule::OverlappingHolds
+            | LintRule::TapDuringHold
+            | LintRule::DuplicateKeyInBundle
+            | LintRule::NoteAfterEnd => Severity::Error,
+            LintRule::MissingEndMark
+            | LintRule::UnusedDivisor
+            | LintRule::ZeroLengthHold
+            | LintRule::RedundantBpm => Severity::Warning,
+        }
+    }
+}
+
+impl std::fmt::Display for LintRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum LintRuleParseError {
+    UnknownRule(String),
+}
+
+impl std::str::FromStr for LintRule {
+    type Err = LintRuleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|rule| rule.id() == s)
+            .ok_or_else(|| LintRuleParseError::UnknownRule(s.to_owned()))
+    }
+}
+
+/// Which rules to run, and how severe their findings are.
+#[derive(Clone, Debug, Default)]
+pub struct LintConfig {
+    // `None` means the rule is disabled
+    overrides: HashMap<LintRule, Option<Severity>>,
+}
+
+impl LintConfig {
+    pub fn set_severity(&mut self, rule: LintRule, severity: Severity) -> &mut Self {
+        self.overrides.insert(rule, Some(severity));
+        self
+    }
+
+    pub fn disable(&mut self, rule: LintRule) -> &mut Self {
+        self.overrides.insert(rule, None);
+        self
+    }
+
+    /// Severity of the rule's findings, or `None` if the rule is disabled.
+    pub fn severity(&self, rule: LintRule) -> Option<Severity> {
+        match self.overrides.get(&rule) {
+            Some(x) => *x,
+            None => Some(rule.default_severity()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct LintDiagnostic {
+    pub rule: LintRule,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl std::fmt::Display for LintDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.rule, self.message)
+    }
+}
+
+/// Runs all enabled rules over a chart's instructions.
+///
+/// The returned diagnostics are sorted by their position in the source.
+pub fn lint_insns<'a, I: IntoIterator<Item = &'a SpRawInsn>>(
+    insns: I,
+    config: &LintConfig,
+) -> Vec<Sp<LintDiagnostic>> {
+    let insns: Vec<_> = insns.into_iter().collect();
+    let mut result = vec![];
+
+    for (rule, finding) in rules::run_all(&insns) {
+        if let Some(severity) = config.severity(rule) {
+            let span = finding.span();
+            let diag = LintDiagnostic {
+                rule,
+                severity,
+                message: finding.into_inner(),
+            };
+            result.push(Sp::new(diag, span));
+        }
+    }
+
+    result.sort_by_key(|diag| diag.span().byte_offset);
+    result
+}
+
+/// Runs all enabled rules over a difficulty of a parsed `maidata.txt`.
+pub fn lint_beatmap(
+    map: &crate::container::AssociatedBeatmapData<'_>,
+    config: &LintConfig,
+) -> Vec<Sp<LintDiagnostic>> {
+    lint_insns(map.iter_insns(), config)
+}
@@ -0,0 +1,226 @@
+use super::LintRule;
+use crate::insn::{Length, RawInsn, RawNoteInsn, SpRawInsn, SpRawNoteInsn};
+use crate::materialize::{MaterializationContext, MaterializedHold, MaterializedTap, Note};
+use crate::{Sp, Span, WithSpan};
+
+/// Timestamps closer than this are considered simultaneous.
+const TS_EPSILON: f32 = 1e-4;
+
+type Finding = (LintRule, Sp<String>);
+
+pub(super) fn run_all(insns: &[&SpRawInsn]) -> Vec<Finding> {
+    let mut result = vec![];
+    check_insn_sequence(insns, &mut result);
+    check_notes(insns, &mut result);
+    check_timeline(insns, &mut result);
+    result
+}
+
+fn finding(rule: LintRule, span: Span, msg: String) -> Finding {
+    (rule, msg.with_span(span))
+}
+
+/// Rules concerning the order of instructions.
+fn check_insn_sequence(insns: &[&SpRawInsn], out: &mut Vec<Finding>) {
+    let mut end_seen = false;
+    // directives not yet followed by anything advancing time
+    let mut pending_bpm: Option<Span> = None;
+    let mut pending_divisor: Option<Span> = None;
+
+    for insn in insns {
+        match &***insn {
+            RawInsn::Bpm(_) => {
+                if let Some(span) = pending_bpm {
+                    out.push(finding(
+                        LintRule::RedundantBpm,
+                        span,
+                        "BPM is changed again before taking effect".to_owned(),
+                    ));
+                }
+                pending_bpm = Some(insn.span());
+            }
+            RawInsn::BeatDivisor(_) => {
+                if let Some(span) = pending_divisor {
+                    out.push(finding(
+                        LintRule::UnusedDivisor,
+                        span,
+                        "beat divisor is changed again before taking effect".to_owned(),
+                    ));
+                }
+                pending_divisor = Some(insn.span());
+                // the divisor is computed from the BPM in effect, which is thus used
+                pending_bpm = None;
+            }
+            RawInsn::Rest | RawInsn::Note(_) | RawInsn::NoteBundle(_) => {
+                pending_bpm = None;
+                pending_divisor = None;
+
+                if end_seen && !matches!(&***insn, RawInsn::Rest) {
+                    out.push(finding(
+                        LintRule::NoteAfterEnd,
+                        insn.span(),
+                        "note placed after the end mark".to_owned(),
+                    ));
+                }
+            }
+            RawInsn::EndMark => {
+                end_seen = true;
+            }
+        }
+    }
+
+    if let Some(span) = pending_divisor {
+        out.push(finding(
+            LintRule::UnusedDivisor,
+            span,
+            "beat divisor is never used".to_owned(),
+        ));
+    }
+
+    if !end_seen {
+        if let Some(last) = insns.last() {
+            out.push(finding(
+                LintRule::MissingEndMark,
+                last.span(),
+                "chart does not end with `E`".to_owned(),
+            ));
+        }
+    }
+}
+
+/// Rules concerning individual notes and bundles.
+fn check_notes(insns: &[&SpRawInsn], out: &mut Vec<Finding>) {
+    for insn in insns {
+        let notes: &[SpRawNoteInsn] = match &***insn {
+            RawInsn::Note(note) => std::slice::from_ref(note),
+            RawInsn::NoteBundle(notes) => notes,
+            _ => continue,
+        };
+
+        let mut seen_keys = vec![];
+        for note in notes {
+            let key = match &**note {
+                RawNoteInsn::Tap(p) => p.key,
+                RawNoteInsn::Hold(p) => p.key,
+                RawNoteInsn::Slide(p) => p.start.key,
+            };
+            if seen_keys.contains(&key) {
+                out.push(finding(
+                    LintRule::DuplicateKeyInBundle,
+                    note.span(),
+                    format!("key {} is already used in this bundle", key.index() + 1),
+                ));
+            } else {
+                seen_keys.push(key);
+            }
+
+            if let RawNoteInsn::Hold(p) = &**note {
+                // holds without a length are short on purpose
+                let is_zero = match p.len {
+                    Some(Length::NumBeats(x)) => x.num == 0,
+                    Some(Length::Seconds(x)) => x <= 0.0,
+                    None => false,
+                };
+                if is_zero {
+                    out.push(finding(
+                        LintRule::ZeroLengthHold,
+                        note.span(),
+                        "hold has zero length".to_owned(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Rules concerning materialized notes.
+fn check_timeline(insns: &[&SpRawInsn], out: &mut Vec<Finding>) {
+    let mut holds: Vec<(MaterializedHold, Span)> = vec![];
+    let mut taps: Vec<(MaterializedTap, Span)> = vec![];
+
+    let mut mcx = MaterializationContext::with_offset(0.0);
+    for insn in insns {
+        for note in mcx.materialize_insns(std::iter::once(*insn)) {
+            match note {
+                Note::Tap(x) => taps.push((x, insn.span())),
+                Note::Hold(x) => holds.push((x, insn.span())),
+                Note::SlideTrack(_) => {}
+            }
+        }
+    }
+
+    for (i, (a, _)) in holds.iter().enumerate() {
+        let a_end = a.ts + a.dur;
+
+        for (b, b_span) in &holds[i + 1..] {
+            if a.key == b.key && b.ts > a.ts + TS_EPSILON && b.ts < a_end - TS_EPSILON {
+                out.push(finding(
+                    LintRule::OverlappingHolds,
+                    *b_span,
+                    format!(
+                        "hold on key {} starts before the previous one ends",
+                        b.key.index() + 1
+                    ),
+                ));
+            }
+        }
+
+        for (tap, tap_span) in &taps {
+            if a.key == tap.key && tap.ts > a.ts + TS_EPSILON && tap.ts < a_end - TS_EPSILON {
+                out.push(finding(
+                    LintRule::TapDuringHold,
+                    *tap_span,
+                    format!("key {} is being held at this time", tap.key.index() + 1),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules_of(x: &str) -> Vec<LintRule> {
+        let insns = crate::insn::parse_insns(x);
+        let insns: Vec<_> = insns.iter().collect();
+        run_all(&insns).into_iter().map(|(rule, _)| rule).collect()
+    }
+
+    #[test]
+    fn test_clean_chart() {
+        assert_eq!(rules_of("(120){4}1,2h[4:1],3-7[4:1],4/5,6h,E"), vec![]);
+    }
+
+    #[test]
+    fn test_sequence_rules() {
+        assert_eq!(rules_of("(120)(150){4}1,E"), vec![LintRule::RedundantBpm]);
+        assert_eq!(rules_of("(120){4}{8}1,E"), vec![LintRule::UnusedDivisor]);
+        assert_eq!(rules_of("(120){4}1,"), vec![LintRule::MissingEndMark]);
+        assert_eq!(rules_of("(120){4}1,E2,"), vec![LintRule::NoteAfterEnd]);
+    }
+
+    #[test]
+    fn test_note_rules() {
+        assert_eq!(
+            rules_of("(120){4}1/1h[4:1],E"),
+            vec![LintRule::DuplicateKeyInBundle]
+        );
+        assert_eq!(
+            rules_of("(120){4}1h[4:0],E"),
+            vec![LintRule::ZeroLengthHold]
+        );
+    }
+
+    #[test]
+    fn test_timeline_rules() {
+        assert_eq!(
+            rules_of("(120){4}1h[1:1],1h[4:1],,,,E"),
+            vec![LintRule::OverlappingHolds]
+        );
+        assert_eq!(
+            rules_of("(120){4}1h[1:1],,1,,,E"),
+            vec![LintRule::TapDuringHold]
+        );
+    }
+}
@@ -11,6 +11,7 @@ pub struct MaterializationContext {
     curr_beat_dur: f32,
     curr_note_dur: f32,
     curr_ts: f32,
+    default_hold_len: insn::Length,
 }
 
 impl MaterializationContext {
@@ -19,9 +20,30 @@ impl MaterializationContext {
             curr_beat_dur: 0.0,
             curr_note_dur: 0.0,
             curr_ts: offset_secs,
+            default_hold_len: insn::Length::Seconds(0.0),
         }
     }
 
+    /// Sets the length given to holds written without one, like `1h`. Defaults to zero.
+    pub fn set_default_hold_len(&mut self, len: insn::Length) {
+        self.default_hold_len = len;
+    }
+
+    /// Timestamp of the next note to be materialized, in seconds.
+    pub fn curr_ts(&self) -> f32 {
+        self.curr_ts
+    }
+
+    /// Duration of one beat under the current BPM, in seconds. Zero before any BPM is set.
+    pub fn curr_beat_dur(&self) -> f32 {
+        self.curr_beat_dur
+    }
+
+    /// Time advanced by every note or rest under the current divisor, in seconds.
+    pub fn curr_note_dur(&self) -> f32 {
+        self.curr_note_dur
+    }
+
     /// Materialize a list of raw instructions into notes.
     pub fn materialize_insns<'a, I: IntoIterator<Item = &'a crate::Sp<insn::RawInsn>>>(
         &mut self,
@@ -29,13 +51,21 @@ impl MaterializationContext {
     ) -> Vec<Note> {
         insns
             .into_iter()
-            .map(|insn| self.materialize_raw_insn(insn))
-            .flatten()
+            .flat_map(|insn| self.materialize_raw_insn(insn))
             .collect()
     }
 
+    /// Materialize a list of raw instructions into notes grouped by time, with their EACH status
+    /// determined. See [`group_into_slices`](super::group_into_slices) for details.
+    pub fn materialize_insns_sliced<'a, I: IntoIterator<Item = &'a crate::Sp<insn::RawInsn>>>(
+        &mut self,
+        insns: I,
+    ) -> Vec<super::TimeSlice> {
+        super::group_into_slices(self.materialize_insns(insns))
+    }
+
     /// Read in one raw instruction and materialize into note(s) if applicable.
-    fn materialize_raw_insn(&mut self, insn: &crate::Sp<insn::RawInsn>) -> Vec<Note> {
+    pub fn materialize_raw_insn(&mut self, insn: &crate::Sp<insn::RawInsn>) -> Vec<Note> {
         use std::ops::Deref;
         match insn.deref() {
             insn::RawInsn::Bpm(params) => {
@@ -70,8 +100,7 @@ impl MaterializationContext {
                 let ts = self.advance_time();
                 raw_notes
                     .iter()
-                    .map(|raw_note| self.materialize_raw_note(ts, raw_note))
-                    .flatten()
+                    .flat_map(|raw_note| self.materialize_raw_note(ts, raw_note))
                     .collect()
             }
         }
@@ -101,7 +130,8 @@ impl MaterializationContext {
             }
             insn::RawNoteInsn::Slide(params) => materialize_slide(ts, self.curr_beat_dur, params),
             insn::RawNoteInsn::Hold(params) => {
-                let m_params = materialize_hold_params(ts, self.curr_beat_dur, params);
+                let m_params =
+                    materialize_hold_params(ts, self.curr_beat_dur, self.default_hold_len, params);
                 vec![Note::Hold(m_params)]
             }
         }
@@ -160,10 +190,14 @@ fn materialize_slide_track_params(
     // in simai, stop time is actually encoded (overridden) in the duration spec of individual
     // slide track
     //
-    // take care of this, falling back to beat duration of current bpm
-    let stop_time = match params.len {
-        insn::SlideLength::Simple(_) => beat_dur,
-        insn::SlideLength::Custom(st, _) => stop_time_spec_to_dur(st),
+    // take care of this, falling back to beat duration of current bpm; an overriding BPM also
+    // applies to the length
+    let (stop_time, len_beat_dur) = match params.len {
+        insn::SlideLength::Simple(_) => (beat_dur, beat_dur),
+        insn::SlideLength::Custom(insn::SlideStopTimeSpec::Bpm(bpm), _) => {
+            (bpm_to_beat_dur(bpm), bpm_to_beat_dur(bpm))
+        }
+        insn::SlideLength::Custom(insn::SlideStopTimeSpec::Seconds(x), _) => (x, beat_dur),
     };
 
     let start_ts = ts + stop_time;
@@ -171,7 +205,7 @@ fn materialize_slide_track_params(
     MaterializedSlideTrack {
         ts,
         start_ts,
-        dur: materialize_duration(params.len.slide_duration(), beat_dur),
+        dur: materialize_duration(params.len.slide_duration(), len_beat_dur),
         start: start_key,
         destination: params.destination.key,
         interim: params.interim.map(|x| x.key),
@@ -179,10 +213,15 @@ fn materialize_slide_track_params(
     }
 }
 
-fn materialize_hold_params(ts: f32, beat_dur: f32, p: &insn::HoldParams) -> MaterializedHold {
+fn materialize_hold_params(
+    ts: f32,
+    beat_dur: f32,
+    default_len: insn::Length,
+    p: &insn::HoldParams,
+) -> MaterializedHold {
     MaterializedHold {
         ts,
-        dur: materialize_duration(p.len, beat_dur),
+        dur: materialize_duration(p.len.unwrap_or(default_len), beat_dur),
         key: p.key,
     }
 }
@@ -194,9 +233,58 @@ fn materialize_duration(x: insn::Length, beat_dur: f32) -> f32 {
     }
 }
 
-fn stop_time_spec_to_dur(x: insn::SlideStopTimeSpec) -> f32 {
-    match x {
-        insn::SlideStopTimeSpec::Bpm(override_bpm) => bpm_to_beat_dur(override_bpm),
-        insn::SlideStopTimeSpec::Seconds(x) => x,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_hold_len() {
+        let insns = crate::insn::parse_insns("(120){4}1h,2h/3,E");
+        let hold_durs = |mcx: &mut MaterializationContext| -> Vec<f32> {
+            mcx.materialize_insns(&insns)
+                .into_iter()
+                .filter_map(|x| match x {
+                    Note::Hold(x) => Some(x.dur),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            hold_durs(&mut MaterializationContext::with_offset(0.0)),
+            vec![0.0, 0.0]
+        );
+
+        let mut mcx = MaterializationContext::with_offset(0.0);
+        mcx.set_default_hold_len(insn::Length::NumBeats(insn::NumBeatsParams {
+            divisor: 8,
+            num: 1,
+        }));
+        assert_eq!(hold_durs(&mut mcx), vec![0.25, 0.25]);
+    }
+
+    #[test]
+    fn test_slide_lengths() {
+        // (wait, duration) at BPM 120, where a beat lasts 0.5 seconds
+        let cases = [
+            ("1-5[8:3]", 0.5, 0.75),
+            ("1-5[#1.5]", 0.5, 1.5),
+            ("1-5[160#8:3]", 0.375, 0.5625),
+            ("1-5[160#2]", 0.375, 2.0),
+            ("1-5[3##8:3]", 3.0, 0.75),
+            ("1-5[3##1.5]", 3.0, 1.5),
+        ];
+
+        for &(note, wait, dur) in &cases {
+            let src = format!("(120){{4}}{},E", note);
+            let insns = crate::insn::parse_insns(&src);
+            let notes = MaterializationContext::with_offset(1.0).materialize_insns(&insns);
+            match &notes[1] {
+                Note::SlideTrack(x) => {
+                    assert_eq!((x.start_ts - x.ts, x.dur), (wait, dur), "{}", note);
+                }
+                x => panic!("unexpected note {:?}", x),
+            }
+        }
     }
 }
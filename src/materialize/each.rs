@@ -0,0 +1,109 @@
+use super::{Note, TimestampInSeconds};
+
+/// Notes whose timestamps differ by less than this are considered simultaneous.
+pub const EACH_TS_EPSILON: f32 = 1e-4;
+
+/// All notes to be hit at the same time.
+#[derive(Clone, Debug)]
+pub struct TimeSlice {
+    pub ts: TimestampInSeconds,
+    pub notes: Vec<SlicedNote>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SlicedNote {
+    pub note: Note,
+    /// Whether the note is rendered in the EACH color.
+    pub is_each: bool,
+    /// Indices of the other notes in the same slice that this note pairs with.
+    pub partners: Vec<usize>,
+}
+
+/// Which notes can pair with each other.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum EachCategory {
+    /// TAPs, BREAKs, slide stars and HOLDs.
+    Head,
+    /// Slide tracks, which only pair with other slide tracks.
+    SlideTrack,
+}
+
+impl From<&Note> for EachCategory {
+    fn from(x: &Note) -> Self {
+        match x {
+            Note::Tap(_) | Note::Hold(_) => EachCategory::Head,
+            Note::SlideTrack(_) => EachCategory::SlideTrack,
+        }
+    }
+}
+
+/// Groups notes into time slices, ordered by time, and determines their EACH status.
+///
+/// TAPs, BREAKs, slide stars and HOLDs in the same slice are EACH with each other; slide tracks
+/// launched from the same slice are EACH with each other.
+pub fn group_into_slices(mut notes: Vec<Note>) -> Vec<TimeSlice> {
+    // stable, so notes of the same slice keep their order in the chart
+    notes.sort_by(|a, b| {
+        a.ts()
+            .partial_cmp(&b.ts())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut result: Vec<TimeSlice> = vec![];
+    for note in notes {
+        let ts = note.ts();
+        let sliced = SlicedNote {
+            note,
+            is_each: false,
+            partners: vec![],
+        };
+
+        match result.last_mut() {
+            Some(slice) if ts - slice.ts < EACH_TS_EPSILON => slice.notes.push(sliced),
+            _ => result.push(TimeSlice {
+                ts,
+                notes: vec![sliced],
+            }),
+        }
+    }
+
+    for slice in &mut result {
+        let categories: Vec<EachCategory> = slice.notes.iter().map(|x| (&x.note).into()).collect();
+
+        for (i, note) in slice.notes.iter_mut().enumerate() {
+            note.partners = (0..categories.len())
+                .filter(|&j| j != i && categories[j] == categories[i])
+                .collect();
+            note.is_each = !note.partners.is_empty();
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materialize::MaterializationContext;
+
+    fn slices_of(x: &str) -> Vec<TimeSlice> {
+        let insns = crate::insn::parse_insns(x);
+        let mut mcx = MaterializationContext::with_offset(0.0);
+        group_into_slices(mcx.materialize_insns(&insns))
+    }
+
+    #[test]
+    fn test_each_grouping() {
+        let slices = slices_of("(120){4}1/2h[4:1],3,4-6[4:1]/5-7[4:1],E");
+        assert_eq!(slices.len(), 3);
+
+        assert!(slices[0].notes.iter().all(|x| x.is_each));
+        assert!(!slices[1].notes[0].is_each);
+
+        // two stars pair with each other, and so do the two tracks
+        let third = &slices[2].notes;
+        assert_eq!(third.len(), 4);
+        assert_eq!(third[0].partners, vec![2]);
+        assert_eq!(third[1].partners, vec![3]);
+    }
+}
@@ -1,6 +1,10 @@
 mod context;
+mod each;
+mod stats;
 
 pub use context::*;
+pub use each::*;
+pub use stats::*;
 
 use crate::insn::{Key, SlideShape};
 
@@ -46,3 +50,26 @@ pub struct MaterializedSlideTrack {
     pub interim: Option<Key>,
     pub shape: SlideShape,
 }
+
+impl MaterializedSlideTrack {
+    /// On-screen path of this track, see [`crate::geometry::SlidePath::new`].
+    pub fn path(&self) -> Option<crate::geometry::SlidePath> {
+        crate::geometry::SlidePath::new(self.shape, self.start, self.interim, self.destination)
+    }
+
+    /// Judgment areas and arrow count of this track, see [`crate::geometry::SlideJudgment::new`].
+    pub fn judgment(&self) -> Option<crate::geometry::SlideJudgment> {
+        crate::geometry::SlideJudgment::new(self.shape, self.start, self.interim, self.destination)
+    }
+}
+
+impl Note {
+    /// Timestamp at which the note is to be hit.
+    pub fn ts(&self) -> TimestampInSeconds {
+        match self {
+            Note::Tap(x) => x.ts,
+            Note::Hold(x) => x.ts,
+            Note::SlideTrack(x) => x.ts,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use super::{MaterializedTapShape, Note};
+
+/// Kinds of notes as far as scoring is concerned.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum NoteKind {
+    Tap,
+    Hold,
+    Slide,
+    Break,
+}
+
+impl NoteKind {
+    pub const ALL: &'static [NoteKind] = &[
+        NoteKind::Tap,
+        NoteKind::Hold,
+        NoteKind::Slide,
+        NoteKind::Break,
+    ];
+
+    /// Weight of one note of this kind in the base achievement, relative to a TAP.
+    pub fn base_weight(self) -> u32 {
+        match self {
+            NoteKind::Tap => 1,
+            NoteKind::Hold => 2,
+            NoteKind::Slide => 3,
+            NoteKind::Break => 5,
+        }
+    }
+}
+
+impl From<&Note> for NoteKind {
+    fn from(x: &Note) -> Self {
+        match x {
+            Note::Tap(tap) => match tap.shape {
+                MaterializedTapShape::Break => NoteKind::Break,
+                // slide stars count as regular taps
+                MaterializedTapShape::Ring | MaterializedTapShape::Star => NoteKind::Tap,
+            },
+            Note::Hold(_) => NoteKind::Hold,
+            Note::SlideTrack(_) => NoteKind::Slide,
+        }
+    }
+}
+
+/// Maximum DX score awarded for a single note.
+pub const DX_SCORE_PER_NOTE: u32 = 3;
+
+/// Achievement percentage shared by all notes according to their base weights.
+pub const BASE_ACHIEVEMENT: f32 = 100.0;
+
+/// Extra achievement percentage shared by all BREAKs.
+pub const BREAK_BONUS_ACHIEVEMENT: f32 = 1.0;
+
+/// Note counts and derived scoring figures of a chart.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ChartStats {
+    pub num_taps: u32,
+    pub num_holds: u32,
+    pub num_slides: u32,
+    pub num_breaks: u32,
+}
+
+impl ChartStats {
+    pub fn from_notes(notes: &[Note]) -> Self {
+        let mut result = Self::default();
+        for note in notes {
+            *result.count_mut(note.into()) += 1;
+        }
+        result
+    }
+
+    pub fn count(&self, kind: NoteKind) -> u32 {
+        match kind {
+            NoteKind::Tap => self.num_taps,
+            NoteKind::Hold => self.num_holds,
+            NoteKind::Slide => self.num_slides,
+            NoteKind::Break => self.num_breaks,
+        }
+    }
+
+    fn count_mut(&mut self, kind: NoteKind) -> &mut u32 {
+        match kind {
+            NoteKind::Tap => &mut self.num_taps,
+            NoteKind::Hold => &mut self.num_holds,
+            NoteKind::Slide => &mut self.num_slides,
+            NoteKind::Break => &mut self.num_breaks,
+        }
+    }
+
+    /// Total number of notes, which is also the maximum combo.
+    pub fn total_notes(&self) -> u32 {
+        self.num_taps + self.num_holds + self.num_slides + self.num_breaks
+    }
+
+    pub fn max_combo(&self) -> u32 {
+        self.total_notes()
+    }
+
+    pub fn max_dx_score(&self) -> u32 {
+        self.total_notes() * DX_SCORE_PER_NOTE
+    }
+
+    /// Sum of base weights of all notes.
+    pub fn total_base_weight(&self) -> u32 {
+        NoteKind::ALL
+            .iter()
+            .map(|&kind| self.count(kind) * kind.base_weight())
+            .sum()
+    }
+
+    /// Achievement percentage awarded for one note of the given kind judged CRITICAL PERFECT.
+    ///
+    /// BREAKs include their share of the break bonus. Returns 0 for charts without notes.
+    pub fn achievement_per_note(&self, kind: NoteKind) -> f32 {
+        let total_weight = self.total_base_weight();
+        if total_weight == 0 {
+            return 0.0;
+        }
+
+        let base = BASE_ACHIEVEMENT * kind.base_weight() as f32 / total_weight as f32;
+        match kind {
+            NoteKind::Break if self.num_breaks > 0 => {
+                base + BREAK_BONUS_ACHIEVEMENT / self.num_breaks as f32
+            }
+            _ => base,
+        }
+    }
+
+    /// Highest possible achievement percentage, i.e. 101% if there is any BREAK.
+    pub fn max_achievement(&self) -> f32 {
+        if self.num_breaks > 0 {
+            BASE_ACHIEVEMENT + BREAK_BONUS_ACHIEVEMENT
+        } else {
+            BASE_ACHIEVEMENT
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_achievement_weights() {
+        let insns = crate::insn::parse_insns("(120){4}1,2b/3h[4:1],4-6[4:1],5,6b,E");
+        let notes =
+            crate::materialize::MaterializationContext::with_offset(0.0).materialize_insns(&insns);
+        let stats = ChartStats::from_notes(&notes);
+
+        // the slide star counts as a TAP
+        assert_eq!(
+            (
+                stats.num_taps,
+                stats.num_holds,
+                stats.num_slides,
+                stats.num_breaks
+            ),
+            (3, 1, 1, 2)
+        );
+
+        assert_eq!(stats.max_combo(), 7);
+        assert_eq!(stats.max_dx_score(), 21);
+        assert_eq!(stats.total_base_weight(), 18);
+
+        let total: f32 = NoteKind::ALL
+            .iter()
+            .map(|&kind| stats.achievement_per_note(kind) * stats.count(kind) as f32)
+            .sum();
+        assert!((total - stats.max_achievement()).abs() < 1e-4);
+    }
+}
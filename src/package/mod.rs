@@ -0,0 +1,328 @@
+//! Loading whole songs, i.e. `maidata.txt` along with the media files it goes with.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::container::{DecodeError, Maidata, ParseError, TextEncoding};
+
+const MAIDATA_FILE_NAME: &str = "maidata.txt";
+
+/// Kinds of media files accompanying `maidata.txt`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AssetKind {
+    /// The music, `track.mp3` or `track.ogg`.
+    Track,
+    /// The background image, `bg.jpg` or `bg.png`.
+    Background,
+    /// The background video, `pv.mp4`.
+    Video,
+}
+
+impl AssetKind {
+    pub const ALL: &'static [AssetKind] =
+        &[AssetKind::Track, AssetKind::Background, AssetKind::Video];
+
+    /// File names this kind of asset may have, in order of preference.
+    pub fn file_names(self) -> &'static [&'static str] {
+        match self {
+            AssetKind::Track => &["track.mp3", "track.ogg"],
+            AssetKind::Background => &["bg.jpg", "bg.png"],
+            AssetKind::Video => &["pv.mp4"],
+        }
+    }
+
+    /// Whether songs are expected to come with this kind of asset; the video is optional.
+    pub fn is_required(self) -> bool {
+        !matches!(self, AssetKind::Video)
+    }
+}
+
+/// Where a package is loaded from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PackageSource {
+    Dir(PathBuf),
+    Zip(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum PackageError {
+    Io(std::io::Error),
+    #[cfg(feature = "zip")]
+    Zip(zip::result::ZipError),
+    /// There is no `maidata.txt` in the package.
+    MissingMaidata,
+    Decode(DecodeError),
+    Parse(ParseError),
+}
+
+impl std::fmt::Display for PackageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageError::Io(e) => write!(f, "I/O error: {}", e),
+            #[cfg(feature = "zip")]
+            PackageError::Zip(e) => write!(f, "zip archive error: {}", e),
+            PackageError::MissingMaidata => write!(f, "{} not found", MAIDATA_FILE_NAME),
+            PackageError::Decode(e) => write!(f, "cannot decode {}: {}", MAIDATA_FILE_NAME, e),
+            PackageError::Parse(e) => write!(f, "cannot parse {}: {}", MAIDATA_FILE_NAME, e),
+        }
+    }
+}
+
+impl std::error::Error for PackageError {}
+
+impl From<std::io::Error> for PackageError {
+    fn from(x: std::io::Error) -> Self {
+        PackageError::Io(x)
+    }
+}
+
+#[cfg(feature = "zip")]
+impl From<zip::result::ZipError> for PackageError {
+    fn from(x: zip::result::ZipError) -> Self {
+        PackageError::Zip(x)
+    }
+}
+
+impl From<DecodeError> for PackageError {
+    fn from(x: DecodeError) -> Self {
+        PackageError::Decode(x)
+    }
+}
+
+impl From<ParseError> for PackageError {
+    fn from(x: ParseError) -> Self {
+        PackageError::Parse(x)
+    }
+}
+
+/// A simai song: the parsed `maidata.txt` and the assets found next to it.
+///
+/// File names are matched case-insensitively, so `Track.MP3` is found as well.
+#[derive(Clone, Debug)]
+pub struct SongPackage {
+    source: PackageSource,
+    maidata: Maidata,
+    encoding: TextEncoding,
+    assets: HashMap<AssetKind, PathBuf>,
+}
+
+impl SongPackage {
+    /// Loads the song in the directory `path`.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> Result<Self, PackageError> {
+        let path = path.as_ref();
+
+        let mut files = vec![];
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        let maidata_name =
+            find_file(&files, MAIDATA_FILE_NAME).ok_or(PackageError::MissingMaidata)?;
+        let bytes = std::fs::read(path.join(maidata_name))?;
+
+        Self::new(
+            PackageSource::Dir(path.to_owned()),
+            &bytes,
+            find_assets(&files, |x| path.join(x)),
+        )
+    }
+
+    /// Loads the song in the zip archive `path`.
+    ///
+    /// `maidata.txt` may be at the root of the archive or in a directory, as happens when a song
+    /// directory is zipped as a whole. Assets are looked for next to it, and their paths are
+    /// those of the entries in the archive.
+    #[cfg(feature = "zip")]
+    pub fn from_zip<P: AsRef<Path>>(path: P) -> Result<Self, PackageError> {
+        use std::io::Read;
+
+        let path = path.as_ref();
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+
+        let entry_names: Vec<_> = archive.file_names().map(str::to_owned).collect();
+        let split = |x: &str| match x.rfind('/') {
+            Some(i) => (x[..=i].to_owned(), x[i + 1..].to_owned()),
+            None => (String::new(), x.to_owned()),
+        };
+
+        // take the outermost maidata.txt, in case there are more
+        let (dir, maidata_name) = entry_names
+            .iter()
+            .map(|x| split(x))
+            .filter(|(_, name)| name.eq_ignore_ascii_case(MAIDATA_FILE_NAME))
+            .min_by_key(|(dir, _)| dir.matches('/').count())
+            .ok_or(PackageError::MissingMaidata)?;
+
+        let mut bytes = vec![];
+        archive
+            .by_name(&format!("{}{}", dir, maidata_name))?
+            .read_to_end(&mut bytes)?;
+
+        let files: Vec<_> = entry_names
+            .iter()
+            .filter_map(|x| x.strip_prefix(dir.as_str()))
+            .filter(|x| !x.is_empty() && !x.contains('/'))
+            .map(str::to_owned)
+            .collect();
+
+        Self::new(
+            PackageSource::Zip(path.to_owned()),
+            &bytes,
+            find_assets(&files, |x| PathBuf::from(format!("{}{}", dir, x))),
+        )
+    }
+
+    fn new(
+        source: PackageSource,
+        maidata_bytes: &[u8],
+        assets: HashMap<AssetKind, PathBuf>,
+    ) -> Result<Self, PackageError> {
+        let (text, encoding) = crate::container::decode(maidata_bytes, None)?;
+        let maidata = crate::container::parse_maidata(&text)?;
+
+        Ok(Self {
+            source,
+            maidata,
+            encoding,
+            assets,
+        })
+    }
+
+    pub fn source(&self) -> &PackageSource {
+        &self.source
+    }
+
+    pub fn maidata(&self) -> &Maidata {
+        &self.maidata
+    }
+
+    /// The encoding `maidata.txt` was found in.
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Path to the asset of the given kind, if present. For zip archives this is the name of the
+    /// entry in the archive.
+    pub fn asset(&self, kind: AssetKind) -> Option<&Path> {
+        self.assets.get(&kind).map(PathBuf::as_path)
+    }
+
+    /// Kinds of required assets not present in the package.
+    pub fn missing_assets(&self) -> Vec<AssetKind> {
+        AssetKind::ALL
+            .iter()
+            .copied()
+            .filter(|kind| kind.is_required() && !self.assets.contains_key(kind))
+            .collect()
+    }
+}
+
+fn find_file<'a>(files: &'a [String], name: &str) -> Option<&'a str> {
+    files
+        .iter()
+        .find(|x| x.eq_ignore_ascii_case(name))
+        .map(String::as_str)
+}
+
+fn find_assets<F: Fn(&str) -> PathBuf>(
+    files: &[String],
+    to_path: F,
+) -> HashMap<AssetKind, PathBuf> {
+    AssetKind::ALL
+        .iter()
+        .filter_map(|&kind| {
+            kind.file_names()
+                .iter()
+                .find_map(|name| find_file(files, name))
+                .map(|x| (kind, to_path(x)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIDATA: &str = "&title=foo\n&inote_5=(120){4}1,E\n";
+
+    fn make_temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("maidata-rs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = make_temp_dir("package");
+        std::fs::write(dir.join("MaiData.TXT"), MAIDATA).unwrap();
+        std::fs::write(dir.join("Track.ogg"), b"").unwrap();
+        std::fs::write(dir.join("pv.mp4"), b"").unwrap();
+        std::fs::create_dir(dir.join("bg.png")).unwrap();
+
+        let pkg = SongPackage::from_dir(&dir).unwrap();
+        assert_eq!(pkg.maidata().title(), "foo");
+        assert_eq!(pkg.encoding(), TextEncoding::Utf8);
+        assert_eq!(
+            pkg.asset(AssetKind::Track),
+            Some(dir.join("Track.ogg").as_path())
+        );
+        assert_eq!(
+            pkg.asset(AssetKind::Video),
+            Some(dir.join("pv.mp4").as_path())
+        );
+        // directories do not count
+        assert_eq!(pkg.asset(AssetKind::Background), None);
+        assert_eq!(pkg.missing_assets(), vec![AssetKind::Background]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_from_dir_errors() {
+        let dir = make_temp_dir("package-errors");
+        assert!(matches!(
+            SongPackage::from_dir(&dir),
+            Err(PackageError::MissingMaidata)
+        ));
+
+        std::fs::write(dir.join("maidata.txt"), "&inote_5=(120){4}zz,E").unwrap();
+        match SongPackage::from_dir(&dir) {
+            Err(PackageError::Parse(ParseError::InvalidValue(key))) => assert_eq!(*key, "inote_5"),
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_from_zip() {
+        use std::io::Write;
+
+        let dir = make_temp_dir("package-zip");
+        let path = dir.join("song.zip");
+
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let options = zip::write::FileOptions::default();
+        zip.start_file("song/maidata.txt", options).unwrap();
+        zip.write_all(MAIDATA.as_bytes()).unwrap();
+        zip.start_file("song/BG.JPG", options).unwrap();
+        zip.start_file("song/extra/track.mp3", options).unwrap();
+        zip.finish().unwrap();
+
+        let pkg = SongPackage::from_zip(&path).unwrap();
+        assert_eq!(pkg.maidata().title(), "foo");
+        assert_eq!(pkg.source(), &PackageSource::Zip(path.clone()));
+        assert_eq!(
+            pkg.asset(AssetKind::Background),
+            Some(Path::new("song/BG.JPG"))
+        );
+        assert_eq!(pkg.missing_assets(), vec![AssetKind::Track]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
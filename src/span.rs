@@ -3,7 +3,11 @@ pub(crate) type NomSpan<'a> = nom_locate::LocatedSpan<&'a str>;
 /// Convenient alias for parsing result with spans.
 pub(crate) type PResult<'a, T> = nom::IResult<NomSpan<'a>, T>;
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+/// Location of a piece of source text.
+///
+/// The default value is an empty span at the very beginning, used for synthesized items
+/// without any source.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
 pub struct Span {
     pub byte_offset: usize,
     pub line: usize,
@@ -33,6 +37,43 @@ impl Span {
             len,
         }
     }
+
+    /// Returns the text covered by the span, given the source it was computed on.
+    ///
+    /// Panics if the span lies outside `src`, as slicing would.
+    pub fn slice<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.byte_offset..self.byte_offset + self.len]
+    }
+
+    /// Returns whether `other` lies entirely within this span.
+    pub fn contains(&self, other: Span) -> bool {
+        self.byte_offset <= other.byte_offset
+            && other.byte_offset + other.len <= self.byte_offset + self.len
+    }
+
+    /// Converts a span computed on a piece of text into one on the enclosing text, given the
+    /// span of that piece in the latter.
+    pub fn rebase(self, origin: Span) -> Self {
+        let rebase_pos = |line: usize, col: usize| {
+            if line == 1 {
+                (origin.line, origin.col + col - 1)
+            } else {
+                (origin.line + line - 1, col)
+            }
+        };
+
+        let (line, col) = rebase_pos(self.line, self.col);
+        let (end_line, end_col) = rebase_pos(self.end_line, self.end_col);
+
+        Self {
+            byte_offset: origin.byte_offset + self.byte_offset,
+            line,
+            col,
+            end_line,
+            end_col,
+            len: self.len,
+        }
+    }
 }
 
 impl From<(NomSpan<'_>, NomSpan<'_>)> for Span {
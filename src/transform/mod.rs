@@ -0,0 +1,11 @@
+//! Operations producing modified charts.
+
+mod normalize;
+mod requantize;
+mod symmetry;
+mod tempo;
+
+pub use normalize::*;
+pub use requantize::*;
+pub use symmetry::*;
+pub use tempo::*;
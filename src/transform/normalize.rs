@@ -0,0 +1,295 @@
+use crate::insn::{
+    BeatDivisorParams, Length, NumBeatsParams, RawInsn, RawNoteInsn, SlideLength,
+    SlideStopTimeSpec, SpRawInsn, SpRawNoteInsn,
+};
+use crate::{Sp, Span, WithSpan};
+
+/// Default maximum error, in seconds, of a beat-relative replacement.
+pub const DEFAULT_NORMALIZE_TOLERANCE: f32 = 1e-4;
+
+/// Default largest divisor a beat-relative replacement may use.
+pub const DEFAULT_NORMALIZE_MAX_DIVISOR: u8 = 64;
+
+/// Reasons an absolute duration was left unchanged.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NormalizeFailure {
+    /// No BPM is in effect where the duration appears.
+    NoTempo,
+    /// No beat-relative form is within tolerance of the duration, given in seconds.
+    NoEquivalent(f32),
+    /// The absolute note duration stays in effect across a tempo change, where a beat-relative
+    /// one would follow the new BPM.
+    TempoChangeFollows,
+}
+
+impl std::fmt::Display for NormalizeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NormalizeFailure::NoTempo => write!(f, "no BPM is in effect"),
+            NormalizeFailure::NoEquivalent(x) => {
+                write!(f, "no beat-relative equivalent of {} seconds", x)
+            }
+            NormalizeFailure::TempoChangeFollows => {
+                write!(f, "note duration outlives a tempo change")
+            }
+        }
+    }
+}
+
+/// Rewrites `{#x}` beat divisors and `[#x]` lengths into their beat-relative forms.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DurationNormalizer {
+    tolerance: f32,
+    max_divisor: u8,
+}
+
+impl Default for DurationNormalizer {
+    fn default() -> Self {
+        Self {
+            tolerance: DEFAULT_NORMALIZE_TOLERANCE,
+            max_divisor: DEFAULT_NORMALIZE_MAX_DIVISOR,
+        }
+    }
+}
+
+impl DurationNormalizer {
+    /// Accepts replacements differing from the original duration by at most `tolerance`
+    /// seconds, and using divisors no larger than `max_divisor`.
+    ///
+    /// Almost any duration has a close rational approximation with a large enough divisor, so
+    /// the latter should be kept to values charters actually use.
+    pub fn new(tolerance: f32, max_divisor: u8) -> Self {
+        Self {
+            tolerance,
+            max_divisor,
+        }
+    }
+
+    pub fn tolerance(self) -> f32 {
+        self.tolerance
+    }
+
+    pub fn max_divisor(self) -> u8 {
+        self.max_divisor
+    }
+
+    /// Normalizes a list of instructions, returning them along with the durations that could not
+    /// be converted.
+    pub fn apply_insns<'a, I: IntoIterator<Item = &'a SpRawInsn>>(
+        self,
+        insns: I,
+    ) -> (Vec<SpRawInsn>, Vec<Sp<NormalizeFailure>>) {
+        let mut failures = vec![];
+        let mut bpm: Option<f32> = None;
+
+        let insns: Vec<_> = insns.into_iter().collect();
+        let result = insns
+            .iter()
+            .enumerate()
+            .map(|(i, &insn)| {
+                let new_insn = match &**insn {
+                    RawInsn::Bpm(p) => {
+                        bpm = Some(p.new_bpm);
+                        insn.clone().into_inner()
+                    }
+                    RawInsn::BeatDivisor(BeatDivisorParams::NewAbsoluteDuration(x)) => {
+                        let divisor = self.find_divisor(bpm, *x).and_then(|d| {
+                            if tempo_changes_before_next_divisor(&insns[i + 1..]) {
+                                Err(NormalizeFailure::TempoChangeFollows)
+                            } else {
+                                Ok(d)
+                            }
+                        });
+                        match divisor {
+                            Ok(d) => RawInsn::BeatDivisor(BeatDivisorParams::NewDivisor(d)),
+                            Err(e) => {
+                                failures.push(e.with_span(insn.span()));
+                                insn.clone().into_inner()
+                            }
+                        }
+                    }
+                    RawInsn::Note(note) => RawInsn::Note(self.apply_note(bpm, note, &mut failures)),
+                    RawInsn::NoteBundle(notes) => RawInsn::NoteBundle(
+                        notes
+                            .iter()
+                            .map(|note| self.apply_note(bpm, note, &mut failures))
+                            .collect(),
+                    ),
+                    _ => insn.clone().into_inner(),
+                };
+                new_insn.with_span(insn.span())
+            })
+            .collect();
+
+        (result, failures)
+    }
+
+    fn apply_note(
+        self,
+        bpm: Option<f32>,
+        note: &SpRawNoteInsn,
+        failures: &mut Vec<Sp<NormalizeFailure>>,
+    ) -> SpRawNoteInsn {
+        let new_note = match &**note {
+            RawNoteInsn::Tap(_) => note.clone().into_inner(),
+            RawNoteInsn::Hold(p) => {
+                let mut p = *p;
+                p.len = p.len.map(|x| self.apply_len(bpm, x, note.span(), failures));
+                RawNoteInsn::Hold(p)
+            }
+            RawNoteInsn::Slide(p) => {
+                let mut p = p.clone();
+                for track in &mut p.tracks {
+                    let mut params = track.params().clone();
+                    params.len = match params.len {
+                        SlideLength::Simple(len) => {
+                            SlideLength::Simple(self.apply_len(bpm, len, track.span(), failures))
+                        }
+                        // beat-relative lengths are counted at the overriding BPM, if any
+                        SlideLength::Custom(SlideStopTimeSpec::Bpm(x), len) => SlideLength::Custom(
+                            SlideStopTimeSpec::Bpm(x),
+                            self.apply_len(Some(x), len, track.span(), failures),
+                        ),
+                        SlideLength::Custom(stop_time @ SlideStopTimeSpec::Seconds(_), len) => {
+                            SlideLength::Custom(
+                                stop_time,
+                                self.apply_len(bpm, len, track.span(), failures),
+                            )
+                        }
+                    };
+                    **track = crate::insn::SlideTrack::new(track.shape(), params);
+                }
+                RawNoteInsn::Slide(p)
+            }
+        };
+
+        new_note.with_span(note.span())
+    }
+
+    fn apply_len(
+        self,
+        bpm: Option<f32>,
+        len: Length,
+        span: Span,
+        failures: &mut Vec<Sp<NormalizeFailure>>,
+    ) -> Length {
+        match len {
+            Length::NumBeats(_) => len,
+            Length::Seconds(x) => match self.find_num_beats(bpm, x) {
+                Ok(p) => Length::NumBeats(p),
+                Err(e) => {
+                    failures.push(e.with_span(span));
+                    len
+                }
+            },
+        }
+    }
+
+    /// Finds `d` such that `{d}` lasts `dur` seconds at the given BPM.
+    fn find_divisor(self, bpm: Option<f32>, dur: f32) -> Result<u8, NormalizeFailure> {
+        let bar_dur = bar_duration(bpm)?;
+
+        let d = (bar_dur / dur).round();
+        if d >= 1.0 && d <= self.max_divisor as f32 && (bar_dur / d - dur).abs() <= self.tolerance {
+            Ok(d as u8)
+        } else {
+            Err(NormalizeFailure::NoEquivalent(dur))
+        }
+    }
+
+    /// Finds the `[d:n]` with the smallest `d` lasting `dur` seconds at the given BPM.
+    pub(crate) fn find_num_beats(
+        self,
+        bpm: Option<f32>,
+        dur: f32,
+    ) -> Result<NumBeatsParams, NormalizeFailure> {
+        let bar_dur = bar_duration(bpm)?;
+        let ratio = dur / bar_dur;
+
+        for divisor in 1..=self.max_divisor {
+            let num = (ratio * divisor as f32).round();
+            if num < 0.0 || num > u8::MAX as f32 {
+                continue;
+            }
+
+            let err = (num / divisor as f32 - ratio).abs() * bar_dur;
+            if err <= self.tolerance {
+                return Ok(NumBeatsParams {
+                    divisor,
+                    num: num as u8,
+                });
+            }
+        }
+
+        Err(NormalizeFailure::NoEquivalent(dur))
+    }
+}
+
+/// Whether a tempo change comes before the next beat divisor instruction, which would change the
+/// note duration of `{d}` but not that of `{#x}`.
+fn tempo_changes_before_next_divisor(insns: &[&SpRawInsn]) -> bool {
+    insns
+        .iter()
+        .map(|x| &***x)
+        .take_while(|x| !matches!(x, RawInsn::BeatDivisor(_)))
+        .any(|x| matches!(x, RawInsn::Bpm(_)))
+}
+
+/// Duration of 4 beats, i.e. that of `[1:1]`.
+fn bar_duration(bpm: Option<f32>) -> Result<f32, NormalizeFailure> {
+    match bpm {
+        Some(bpm) if bpm > 0.0 => Ok(240.0 / bpm),
+        _ => Err(NormalizeFailure::NoTempo),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(x: &str) -> (String, Vec<NormalizeFailure>) {
+        let insns = crate::insn::parse_insns(x);
+        let (insns, failures) = DurationNormalizer::default().apply_insns(&insns);
+        (
+            crate::insn::serialize_insns(&insns),
+            failures.into_iter().map(Sp::into_inner).collect(),
+        )
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("(120){#0.125}1h[#1.5]/2-6[#0.5],E"),
+            ("(120){16}1h[4:3]/2-6[4:1],E".to_owned(), vec![]),
+        );
+        // lengths after a BPM are counted at that BPM
+        assert_eq!(
+            normalize("(120){4}3-7[3##1.5],4-8[160#1.5],E"),
+            ("(120){4}3-7[3##4:3],4-8[160#1:1],E".to_owned(), vec![]),
+        );
+        // the absolute duration would not follow the tempo change, unlike `{8}`
+        assert_eq!(
+            normalize("(120){#0.25}1,(240)2,{#0.125}3,E"),
+            (
+                "(120){#0.25}1,\n(240)2,\n{8}3,E".to_owned(),
+                vec![NormalizeFailure::TempoChangeFollows],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_failures() {
+        assert_eq!(
+            normalize("{#0.25}(120){#0.3}1h[#0.1234],3-7[3##0.1234],E"),
+            (
+                "{#0.25}(120){#0.3}1h[#0.1234],3-7[3##0.1234],E".to_owned(),
+                vec![
+                    NormalizeFailure::NoTempo,
+                    NormalizeFailure::NoEquivalent(0.3),
+                    NormalizeFailure::NoEquivalent(0.1234),
+                    NormalizeFailure::NoEquivalent(0.1234),
+                ],
+            ),
+        );
+    }
+}
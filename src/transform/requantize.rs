@@ -0,0 +1,391 @@
+use super::DurationNormalizer;
+use crate::insn::{
+    BeatDivisorParams, BpmParams, HoldParams, Key, Length, RawInsn, RawNoteInsn, SlideLength,
+    SlideParams, SlideStopTimeSpec, SlideTrack, SlideTrackParams, SpRawInsn, SpRawNoteInsn,
+    TapParams, TapVariant,
+};
+use crate::materialize::{
+    group_into_slices, MaterializedSlideTrack, MaterializedTapShape, Note, TimestampInSeconds,
+    EACH_TS_EPSILON,
+};
+use crate::{Span, WithSpan};
+
+/// A BPM change taking effect at the given time.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TempoChange {
+    pub ts: TimestampInSeconds,
+    pub bpm: f32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RequantizeError {
+    /// No tempo is given at all.
+    EmptyTempoMap,
+    /// A note or tempo change happens before the chart's offset, at the given time.
+    BeforeOffset(TimestampInSeconds),
+}
+
+impl std::fmt::Display for RequantizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequantizeError::EmptyTempoMap => write!(f, "tempo map is empty"),
+            RequantizeError::BeforeOffset(ts) => {
+                write!(f, "event at {} happens before the chart's offset", ts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RequantizeError {}
+
+/// Rebuilds raw instructions from materialized notes, i.e. the inverse of
+/// [`MaterializationContext`](crate::materialize::MaterializationContext).
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Requantizer {
+    normalizer: DurationNormalizer,
+}
+
+/// Things happening at the same time.
+struct Event {
+    ts: TimestampInSeconds,
+    bpm: Option<f32>,
+    notes: Vec<Note>,
+}
+
+impl Requantizer {
+    /// Accepts timings differing from the original by at most `tolerance` seconds, and using
+    /// divisors no larger than `max_divisor`. Timings that cannot be expressed this way fall
+    /// back to absolute durations.
+    pub fn new(tolerance: f32, max_divisor: u8) -> Self {
+        Self {
+            normalizer: DurationNormalizer::new(tolerance, max_divisor),
+        }
+    }
+
+    /// Rebuilds instructions that materialize into the given notes when started at `offset`.
+    ///
+    /// The first tempo change applies from the offset on, regardless of its timestamp. The
+    /// synthesized instructions carry empty spans.
+    pub fn requantize(
+        self,
+        notes: &[Note],
+        tempo_map: &[TempoChange],
+        offset: TimestampInSeconds,
+    ) -> Result<Vec<SpRawInsn>, RequantizeError> {
+        let events = collect_events(notes, tempo_map, offset)?;
+
+        let mut result = vec![];
+        let mut emit = |insn: RawInsn| result.push(insn.with_span(Span::default()));
+
+        let mut bpm = None;
+        // note duration in effect, which only changes with beat divisor instructions
+        let mut note_dur: Option<f32> = None;
+
+        for (i, event) in events.iter().enumerate() {
+            if let Some(new_bpm) = event.bpm {
+                emit(RawInsn::Bpm(BpmParams { new_bpm }));
+                bpm = Some(new_bpm);
+            }
+
+            let note_insn = self.notes_to_insn(&event.notes, bpm);
+
+            let gap = match events.get(i + 1) {
+                Some(next) => next.ts - event.ts,
+                None => {
+                    // the last event, only place the notes
+                    if let Some(insn) = note_insn {
+                        emit(insn);
+                    }
+                    break;
+                }
+            };
+
+            let (divisor, steps) = self.fit_gap(bpm, note_dur, gap);
+            if let Some(divisor) = divisor {
+                note_dur = Some(match divisor {
+                    BeatDivisorParams::NewDivisor(d) => bar_duration(bpm) / d as f32,
+                    BeatDivisorParams::NewAbsoluteDuration(x) => x,
+                });
+                emit(RawInsn::BeatDivisor(divisor));
+            }
+
+            let num_rests = match note_insn {
+                Some(insn) => {
+                    emit(insn);
+                    steps - 1
+                }
+                None => steps,
+            };
+            for _ in 0..num_rests {
+                emit(RawInsn::Rest);
+            }
+        }
+
+        emit(RawInsn::EndMark);
+        Ok(result)
+    }
+
+    /// Finds a note duration evenly dividing `gap`, returning the divisor change needed (if any)
+    /// and the number of steps of that duration.
+    fn fit_gap(
+        self,
+        bpm: Option<f32>,
+        note_dur: Option<f32>,
+        gap: f32,
+    ) -> (Option<BeatDivisorParams>, u32) {
+        let steps_of = |dur: f32| -> Option<u32> {
+            let steps = (gap / dur).round();
+            if steps >= 1.0 && (steps * dur - gap).abs() <= self.normalizer.tolerance() {
+                Some(steps as u32)
+            } else {
+                None
+            }
+        };
+
+        // keep the current divisor if possible
+        if let Some(steps) = note_dur.and_then(steps_of) {
+            return (None, steps);
+        }
+
+        if bpm.is_some() {
+            let bar_dur = bar_duration(bpm);
+            for d in 1..=self.normalizer.max_divisor() {
+                if let Some(steps) = steps_of(bar_dur / d as f32) {
+                    return (Some(BeatDivisorParams::NewDivisor(d)), steps);
+                }
+            }
+        }
+
+        (Some(BeatDivisorParams::NewAbsoluteDuration(gap)), 1)
+    }
+
+    fn notes_to_insn(self, notes: &[Note], bpm: Option<f32>) -> Option<RawInsn> {
+        let mut raw_notes: Vec<SpRawNoteInsn> = vec![];
+        let tracks: Vec<&MaterializedSlideTrack> = notes
+            .iter()
+            .filter_map(|x| match x {
+                Note::SlideTrack(t) => Some(t),
+                _ => None,
+            })
+            .collect();
+        let mut used_tracks = vec![false; tracks.len()];
+
+        let mut push_slide = |raw_notes: &mut Vec<SpRawNoteInsn>, start: TapParams| {
+            let slide_tracks: Vec<_> = tracks
+                .iter()
+                .enumerate()
+                .filter(|(i, t)| !used_tracks[*i] && t.start == start.key)
+                .map(|(i, t)| (i, self.slide_track(t, bpm).with_span(Span::default())))
+                .collect();
+            if slide_tracks.is_empty() {
+                return false;
+            }
+
+            for (i, _) in &slide_tracks {
+                used_tracks[*i] = true;
+            }
+            let slide = SlideParams {
+                start,
+                tracks: slide_tracks.into_iter().map(|(_, t)| t).collect(),
+            };
+            raw_notes.push(RawNoteInsn::Slide(slide).with_span(Span::default()));
+            true
+        };
+
+        for note in notes {
+            match note {
+                Note::Tap(tap) => {
+                    let variant = match tap.shape {
+                        MaterializedTapShape::Break => TapVariant::Break,
+                        MaterializedTapShape::Ring | MaterializedTapShape::Star => TapVariant::Tap,
+                    };
+                    let params = TapParams {
+                        variant,
+                        key: tap.key,
+                    };
+
+                    let is_slide = tap.shape == MaterializedTapShape::Star
+                        && push_slide(&mut raw_notes, params);
+                    if !is_slide {
+                        raw_notes.push(RawNoteInsn::Tap(params).with_span(Span::default()));
+                    }
+                }
+                Note::Hold(hold) => {
+                    // zero-length holds are what bare `1h` materializes to by default
+                    let params = HoldParams {
+                        key: hold.key,
+                        len: if hold.dur > 0.0 {
+                            Some(self.length(hold.dur, bpm))
+                        } else {
+                            None
+                        },
+                    };
+                    raw_notes.push(RawNoteInsn::Hold(params).with_span(Span::default()));
+                }
+                Note::SlideTrack(_) => {}
+            }
+        }
+
+        // tracks without a star, which the materializer never produces
+        for key in Key::ALL.iter() {
+            let params = TapParams {
+                variant: TapVariant::Tap,
+                key: *key,
+            };
+            push_slide(&mut raw_notes, params);
+        }
+
+        match raw_notes.len() {
+            0 => None,
+            1 => Some(RawInsn::Note(raw_notes.pop().unwrap())),
+            _ => Some(RawInsn::NoteBundle(raw_notes)),
+        }
+    }
+
+    fn length(self, dur: f32, bpm: Option<f32>) -> Length {
+        match self.normalizer.find_num_beats(bpm, dur) {
+            Ok(p) => Length::NumBeats(p),
+            Err(_) => Length::Seconds(dur),
+        }
+    }
+
+    /// Finds the `[bpm#x:y]` form of a slide's stop time and length, if any is nicer than the
+    /// length `len` at the current BPM.
+    ///
+    /// Only whole BPMs are written, and lengths are then counted at that BPM, so the form is only
+    /// taken if the length needs a smaller divisor that way.
+    fn stop_bpm_len(self, stop_time: f32, dur: f32, len: Length) -> Option<(f32, Length)> {
+        let stop_bpm = (60.0 / stop_time).round();
+        if !stop_bpm.is_finite()
+            || stop_bpm <= 0.0
+            || (60.0 / stop_bpm - stop_time).abs() > self.normalizer.tolerance()
+        {
+            return None;
+        }
+
+        match (self.length(dur, Some(stop_bpm)), len) {
+            (Length::NumBeats(p), Length::NumBeats(q)) if p.divisor >= q.divisor => None,
+            (x @ Length::NumBeats(_), _) => Some((stop_bpm, x)),
+            (Length::Seconds(_), _) => None,
+        }
+    }
+
+    fn slide_track(self, track: &MaterializedSlideTrack, bpm: Option<f32>) -> SlideTrack {
+        let stop_time = track.start_ts - track.ts;
+        let beat_dur = bar_duration(bpm) / 4.0;
+
+        let len = if (stop_time - beat_dur).abs() <= self.normalizer.tolerance() {
+            SlideLength::Simple(self.length(track.dur, bpm))
+        } else {
+            let len = self.length(track.dur, bpm);
+            match self.stop_bpm_len(stop_time, track.dur, len) {
+                Some((stop_bpm, len)) => SlideLength::Custom(SlideStopTimeSpec::Bpm(stop_bpm), len),
+                None => SlideLength::Custom(SlideStopTimeSpec::Seconds(stop_time), len),
+            }
+        };
+
+        let params = SlideTrackParams {
+            destination: TapParams {
+                variant: TapVariant::Tap,
+                key: track.destination,
+            },
+            interim: track.interim.map(|key| TapParams {
+                variant: TapVariant::Tap,
+                key,
+            }),
+            len,
+        };
+        SlideTrack::new(track.shape, params)
+    }
+}
+
+fn collect_events(
+    notes: &[Note],
+    tempo_map: &[TempoChange],
+    offset: TimestampInSeconds,
+) -> Result<Vec<Event>, RequantizeError> {
+    let (first, rest) = tempo_map
+        .split_first()
+        .ok_or(RequantizeError::EmptyTempoMap)?;
+
+    let mut events = vec![Event {
+        ts: offset,
+        bpm: Some(first.bpm),
+        notes: vec![],
+    }];
+    events.extend(rest.iter().map(|x| Event {
+        ts: x.ts,
+        bpm: Some(x.bpm),
+        notes: vec![],
+    }));
+    events.extend(
+        group_into_slices(notes.to_vec())
+            .into_iter()
+            .map(|slice| Event {
+                ts: slice.ts,
+                bpm: None,
+                notes: slice.notes.into_iter().map(|x| x.note).collect(),
+            }),
+    );
+    events.sort_by(|a, b| a.ts.partial_cmp(&b.ts).unwrap_or(std::cmp::Ordering::Equal));
+
+    // merge simultaneous events
+    let mut result: Vec<Event> = vec![];
+    for event in events {
+        if event.ts < offset - EACH_TS_EPSILON {
+            return Err(RequantizeError::BeforeOffset(event.ts));
+        }
+
+        match result.last_mut() {
+            Some(last) if event.ts - last.ts < EACH_TS_EPSILON => {
+                last.bpm = event.bpm.or(last.bpm);
+                last.notes.extend(event.notes);
+            }
+            _ => result.push(event),
+        }
+    }
+
+    Ok(result)
+}
+
+fn bar_duration(bpm: Option<f32>) -> f32 {
+    bpm.map_or(0.0, |bpm| 240.0 / bpm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materialize::MaterializationContext;
+
+    #[test]
+    fn test_roundtrip() {
+        let src = "(120){4}1,,2b/3h[4:1],{8}4-6[4:1]*>8[160#8:3],5,{16}6,7,(150){4}8h[#0.3],1h,\
+                   2-6[3##8:3],3-7[0##4:1],E";
+        let insns = crate::insn::parse_insns(src);
+        let notes = MaterializationContext::with_offset(0.5).materialize_insns(&insns);
+
+        let tempo_map = [
+            TempoChange {
+                ts: 0.0,
+                bpm: 120.0,
+            },
+            TempoChange {
+                ts: 2.75,
+                bpm: 150.0,
+            },
+        ];
+        let requantized = Requantizer::default()
+            .requantize(&notes, &tempo_map, 0.5)
+            .unwrap();
+        assert_eq!(
+            crate::insn::serialize_insns(&requantized),
+            "(120){2}1,\n{4}2b/3h[4:1],\n{8}4-6[4:1]*>8[160#8:3],5,\n{16}6,7,\n(150){4}8h[16:3],1h,2-6[3##8:3],3-7[0##4:1],E",
+        );
+
+        let new_notes = MaterializationContext::with_offset(0.5).materialize_insns(&requantized);
+        assert_eq!(notes.len(), new_notes.len());
+        for (a, b) in notes.iter().zip(new_notes.iter()) {
+            assert!((a.ts() - b.ts()).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::insn::{
+    Key, RawInsn, RawNoteInsn, SlideParams, SlideShape, SlideTrack, SlideTrackParams, SpRawInsn,
+    TapParams, TouchSensor,
+};
+use crate::materialize::Note;
+use crate::Sp;
+
+/// A symmetry of the play area, remapping keys and sensors.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum KeyTransform {
+    /// Swaps the left and right halves, e.g. key 1 with key 8.
+    MirrorHorizontal,
+    /// Swaps the upper and lower halves, e.g. key 1 with key 4.
+    MirrorVertical,
+    /// Rotates clockwise by the given number of keys (counterclockwise if negative).
+    Rotate(i8),
+}
+
+impl KeyTransform {
+    /// Whether the transform reverses the direction of rotation.
+    pub fn is_reflection(self) -> bool {
+        match self {
+            KeyTransform::MirrorHorizontal | KeyTransform::MirrorVertical => true,
+            KeyTransform::Rotate(_) => false,
+        }
+    }
+
+    pub fn apply_key(self, key: Key) -> Key {
+        let idx = key.index() as i16;
+        let new_idx = match self {
+            KeyTransform::MirrorHorizontal => 7 - idx,
+            KeyTransform::MirrorVertical => 3 - idx,
+            KeyTransform::Rotate(n) => idx + n as i16,
+        };
+        Key::from_index(new_idx.rem_euclid(8) as u8)
+    }
+
+    pub fn apply_sensor(self, sensor: TouchSensor) -> TouchSensor {
+        let group = sensor.group();
+        let idx = sensor.index() as i16;
+
+        let new_idx = match group {
+            'C' => return sensor,
+            // A and B sensors face the keys
+            'A' | 'B' => self.apply_key(Key::from_index(idx as u8)).index() as i16,
+            // D and E sensors sit between the keys, with D1 and E1 at 12 o'clock
+            _ => match self {
+                KeyTransform::MirrorHorizontal => 8 - idx,
+                KeyTransform::MirrorVertical => 4 - idx,
+                KeyTransform::Rotate(n) => idx + n as i16,
+            },
+        };
+
+        TouchSensor::from_group_index(group, new_idx.rem_euclid(8) as u8).unwrap()
+    }
+
+    /// Returns the shape a slide track takes after the transform, given the key it starts from
+    /// before the transform.
+    pub fn apply_slide_shape(self, shape: SlideShape, start: Key) -> SlideShape {
+        match shape {
+            SlideShape::CircumferenceLeft | SlideShape::CircumferenceRight => {
+                // the symbol of these shapes depends on the half of the ring the track starts
+                // from, so work with the actual direction instead
+                let is_right = shape == SlideShape::CircumferenceRight;
+                let clockwise = (start.is_upper_half() == is_right) != self.is_reflection();
+
+                if clockwise == self.apply_key(start).is_upper_half() {
+                    SlideShape::CircumferenceRight
+                } else {
+                    SlideShape::CircumferenceLeft
+                }
+            }
+            _ if !self.is_reflection() => shape,
+            SlideShape::P => SlideShape::Q,
+            SlideShape::Q => SlideShape::P,
+            SlideShape::S => SlideShape::Z,
+            SlideShape::Z => SlideShape::S,
+            SlideShape::Pp => SlideShape::Qq,
+            SlideShape::Qq => SlideShape::Pp,
+            _ => shape,
+        }
+    }
+
+    fn apply_tap_params(self, p: &TapParams) -> TapParams {
+        TapParams {
+            variant: p.variant,
+            key: self.apply_key(p.key),
+        }
+    }
+
+    pub fn apply_slide(self, p: &SlideParams) -> SlideParams {
+        let tracks = p
+            .tracks
+            .iter()
+            .map(|track| {
+                let params = track.params();
+                let new_params = SlideTrackParams {
+                    destination: self.apply_tap_params(&params.destination),
+                    interim: params.interim.map(|x| self.apply_tap_params(&x)),
+                    len: params.len,
+                };
+                let shape = self.apply_slide_shape(track.shape(), p.start.key);
+                Sp::new(SlideTrack::new(shape, new_params), track.span())
+            })
+            .collect();
+
+        SlideParams {
+            start: self.apply_tap_params(&p.start),
+            tracks,
+        }
+    }
+
+    pub fn apply_note_insn(self, note: &RawNoteInsn) -> RawNoteInsn {
+        match note {
+            RawNoteInsn::Tap(p) => RawNoteInsn::Tap(self.apply_tap_params(p)),
+            RawNoteInsn::Hold(p) => {
+                let mut p = *p;
+                p.key = self.apply_key(p.key);
+                RawNoteInsn::Hold(p)
+            }
+            RawNoteInsn::Slide(p) => RawNoteInsn::Slide(self.apply_slide(p)),
+        }
+    }
+
+    pub fn apply_insn(self, insn: &RawInsn) -> RawInsn {
+        let apply_sp = |note: &Sp<RawNoteInsn>| Sp::new(self.apply_note_insn(note), note.span());
+
+        match insn {
+            RawInsn::Note(note) => RawInsn::Note(apply_sp(note)),
+            RawInsn::NoteBundle(notes) => RawInsn::NoteBundle(notes.iter().map(apply_sp).collect()),
+            _ => insn.clone(),
+        }
+    }
+
+    /// Transforms a list of instructions, keeping their spans.
+    pub fn apply_insns<'a, I: IntoIterator<Item = &'a SpRawInsn>>(
+        self,
+        insns: I,
+    ) -> Vec<SpRawInsn> {
+        insns
+            .into_iter()
+            .map(|insn| Sp::new(self.apply_insn(insn), insn.span()))
+            .collect()
+    }
+
+    pub fn apply_note(self, note: &Note) -> Note {
+        let mut note = *note;
+        match &mut note {
+            Note::Tap(x) => x.key = self.apply_key(x.key),
+            Note::Hold(x) => x.key = self.apply_key(x.key),
+            Note::SlideTrack(x) => {
+                x.shape = self.apply_slide_shape(x.shape, x.start);
+                x.start = self.apply_key(x.start);
+                x.destination = self.apply_key(x.destination);
+                x.interim = x.interim.map(|k| self.apply_key(k));
+            }
+        }
+        note
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(x: &str, t: KeyTransform) -> String {
+        let insns = crate::insn::parse_insns(x);
+        crate::insn::serialize_insns(&t.apply_insns(&insns))
+    }
+
+    #[test]
+    fn test_mirror() {
+        let src = "1,2b/7h[4:1],1>3[4:1],1p5[4:1],1s5[4:1],1V35[4:1],";
+        assert_eq!(
+            transform(src, KeyTransform::MirrorHorizontal),
+            "8,7b/2h[4:1],8<6[4:1],8q4[4:1],8z4[4:1],8V64[4:1],",
+        );
+        assert_eq!(
+            transform(src, KeyTransform::MirrorVertical),
+            "4,3b/6h[4:1],4>2[4:1],4q8[4:1],4z8[4:1],4V28[4:1],",
+        );
+    }
+
+    #[test]
+    fn test_rotate() {
+        assert_eq!(
+            transform("1>3[4:1],8pp1[4:1],", KeyTransform::Rotate(2)),
+            "3<5[4:1],2pp3[4:1],",
+        );
+    }
+
+    #[test]
+    fn test_sensors() {
+        use TouchSensor::*;
+
+        let t = KeyTransform::MirrorHorizontal;
+        assert_eq!(t.apply_sensor(A1), A8);
+        assert_eq!(t.apply_sensor(C), C);
+        assert_eq!(t.apply_sensor(D1), D1);
+        assert_eq!(t.apply_sensor(E2), E8);
+        assert_eq!(KeyTransform::MirrorVertical.apply_sensor(D1), D5);
+        assert_eq!(KeyTransform::Rotate(-1).apply_sensor(B1), B8);
+    }
+}
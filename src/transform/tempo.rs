@@ -0,0 +1,135 @@
+use crate::insn::{
+    BeatDivisorParams, BpmParams, Length, RawInsn, RawNoteInsn, SlideLength, SlideParams,
+    SlideStopTimeSpec, SpRawInsn,
+};
+use crate::Sp;
+
+/// A change of playback rate, e.g. 0.75 for playing at 75% speed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TempoScale {
+    rate: f32,
+}
+
+impl TempoScale {
+    /// Returns `None` if the rate is not a positive finite number.
+    pub fn new(rate: f32) -> Option<Self> {
+        if rate.is_finite() && rate > 0.0 {
+            Some(Self { rate })
+        } else {
+            None
+        }
+    }
+
+    pub fn rate(self) -> f32 {
+        self.rate
+    }
+
+    pub fn apply_bpm(self, bpm: f32) -> f32 {
+        bpm * self.rate
+    }
+
+    /// Scales an absolute duration or timestamp, in seconds.
+    pub fn apply_duration(self, secs: f32) -> f32 {
+        secs / self.rate
+    }
+
+    fn apply_len(self, len: Length) -> Length {
+        match len {
+            // beat-relative lengths follow the BPM automatically
+            Length::NumBeats(_) => len,
+            Length::Seconds(x) => Length::Seconds(self.apply_duration(x)),
+        }
+    }
+
+    fn apply_slide_len(self, len: SlideLength) -> SlideLength {
+        match len {
+            SlideLength::Simple(l) => SlideLength::Simple(self.apply_len(l)),
+            SlideLength::Custom(stop_time, l) => {
+                let stop_time = match stop_time {
+                    SlideStopTimeSpec::Bpm(x) => SlideStopTimeSpec::Bpm(self.apply_bpm(x)),
+                    SlideStopTimeSpec::Seconds(x) => {
+                        SlideStopTimeSpec::Seconds(self.apply_duration(x))
+                    }
+                };
+                SlideLength::Custom(stop_time, self.apply_len(l))
+            }
+        }
+    }
+
+    pub fn apply_slide(self, p: &SlideParams) -> SlideParams {
+        let mut p = p.clone();
+        for track in &mut p.tracks {
+            let mut params = track.params().clone();
+            params.len = self.apply_slide_len(params.len);
+            **track = crate::insn::SlideTrack::new(track.shape(), params);
+        }
+        p
+    }
+
+    pub fn apply_note_insn(self, note: &RawNoteInsn) -> RawNoteInsn {
+        match note {
+            RawNoteInsn::Tap(_) => note.clone(),
+            RawNoteInsn::Hold(p) => {
+                let mut p = *p;
+                p.len = p.len.map(|x| self.apply_len(x));
+                RawNoteInsn::Hold(p)
+            }
+            RawNoteInsn::Slide(p) => RawNoteInsn::Slide(self.apply_slide(p)),
+        }
+    }
+
+    pub fn apply_insn(self, insn: &RawInsn) -> RawInsn {
+        let apply_sp = |note: &Sp<RawNoteInsn>| Sp::new(self.apply_note_insn(note), note.span());
+
+        match insn {
+            RawInsn::Bpm(p) => RawInsn::Bpm(BpmParams {
+                new_bpm: self.apply_bpm(p.new_bpm),
+            }),
+            RawInsn::BeatDivisor(BeatDivisorParams::NewAbsoluteDuration(x)) => {
+                RawInsn::BeatDivisor(BeatDivisorParams::NewAbsoluteDuration(
+                    self.apply_duration(*x),
+                ))
+            }
+            RawInsn::Note(note) => RawInsn::Note(apply_sp(note)),
+            RawInsn::NoteBundle(notes) => RawInsn::NoteBundle(notes.iter().map(apply_sp).collect()),
+            _ => insn.clone(),
+        }
+    }
+
+    /// Scales a list of instructions, keeping their spans.
+    pub fn apply_insns<'a, I: IntoIterator<Item = &'a SpRawInsn>>(
+        self,
+        insns: I,
+    ) -> Vec<SpRawInsn> {
+        insns
+            .into_iter()
+            .map(|insn| Sp::new(self.apply_insn(insn), insn.span()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materialize::MaterializationContext;
+
+    #[test]
+    fn test_scaled_timestamps() {
+        let src = "(120){4}1,2h[#1],{#0.25}3-7[160#4:1],4-8[3##1.5],E";
+        let insns = crate::insn::parse_insns(src);
+
+        let scale = TempoScale::new(0.75).unwrap();
+        let scaled = scale.apply_insns(&insns);
+        assert_eq!(
+            crate::insn::serialize_insns(&scaled),
+            "(90){4}1,2h[#1.3333334],\n{#0.33333334}3-7[120#4:1],4-8[4##2],E",
+        );
+
+        let orig_notes = MaterializationContext::with_offset(1.0).materialize_insns(&insns);
+        let new_notes = MaterializationContext::with_offset(scale.apply_duration(1.0))
+            .materialize_insns(&scaled);
+        for (a, b) in orig_notes.iter().zip(new_notes.iter()) {
+            assert!((scale.apply_duration(a.ts()) - b.ts()).abs() < 1e-4);
+        }
+    }
+}